@@ -6,7 +6,13 @@ use wrong_password_finder::{new_records,
 };
 
 fn main() {
-    let records = new_records("test_data/input.txt");
+    let records = match new_records("test_data/input.txt") {
+        Ok(records) => records,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
     println!("part 1 result is {}", find_number_of_valid_records(&records, &policy_pt1));
     println!("part 2 result is {}", find_number_of_valid_records(&records, &policy_pt2));
 }
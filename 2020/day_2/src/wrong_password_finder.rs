@@ -2,66 +2,138 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use std::collections::HashSet;
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
 
-pub fn new_records(filepath: &str) -> HashSet<Record> {
+pub fn new_records(filepath: &str) -> Result<HashSet<Record>, ReadError> {
     let mut records: HashSet<Record> = HashSet::new();
-        if let Ok(lines) = read_lines(filepath) {
-            for line in lines {
-                if let Ok(info) = line {
-                    records.insert(Record::new(&info));
-                }
-            }
-        }
-    records
+    for (index, info) in read_lines(filepath).map_err(ReadError::Io)?.enumerate() {
+        let info = info.map_err(ReadError::Io)?;
+        let record = Record::new(&info).map_err(|kind| ReadError::Parse(ParseError { line: index + 1, kind }))?;
+        records.insert(record);
+    }
+    Ok(records)
 }
 
 pub fn find_number_of_valid_records(records: &HashSet<Record>, policy: &dyn Fn(&Record) -> bool ) -> usize {
     records.iter().filter(|record| policy(record)).count()
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ParseErrorKind {
+    MissingPassword,
+    MissingTarget,
+    InvalidLimits(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::MissingPassword => write!(f, "line {}: expected \"<policy>: <password>\"", self.line),
+            ParseErrorKind::MissingTarget => write!(f, "line {}: policy has no target", self.line),
+            ParseErrorKind::InvalidLimits(limits) => write!(f, "line {}: invalid limits \"{}\"", self.line, limits),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "{}", error),
+            ReadError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug)]
 pub struct Record {
     password: String,
     constraints: Constraints,
 }
 
 impl Record {
-    fn new(info: &str) -> Self {
-        let info: Vec<&str> = info.split(": ").collect();
-        Record {
-            password: info[1].to_string(),
-            constraints: Constraints::new(info[0]),
-        }
+    fn new(info: &str) -> Result<Self, ParseErrorKind> {
+        let (constraints, password) = info.split_once(": ").ok_or(ParseErrorKind::MissingPassword)?;
+        Ok(Record {
+            password: password.to_string(),
+            constraints: Constraints::new(constraints)?,
+        })
+    }
+
+    // Whether the target starts at the given 1-based grapheme position, or None if
+    // the target would not fit in the password there.
+    fn target_at(&self, position: usize) -> Option<bool> {
+        let graphemes: Vec<&str> = self.password.graphemes(true).collect();
+        let target: Vec<&str> = self.constraints.target.graphemes(true).collect();
+        let start = position.checked_sub(1)?;
+        let candidate = graphemes.get(start..start + target.len())?;
+        Some(candidate == target.as_slice())
     }
 }
 
 #[derive(PartialEq, Eq, Hash, Debug)]
 struct Constraints {
-    nums: (u8, u8),
-    target_letter: char,
+    nums: (usize, usize),
+    target: String,
 }
 
 impl Constraints {
-    fn new(info: &str) -> Self {
-        let info: Vec<&str> = info.split_whitespace().collect();
-        let limits: Vec<&str> = info[0].split("-").collect();
-        Self { 
-            nums: (limits[0].parse().unwrap(), limits[1].parse().unwrap()),
-            target_letter: info[1].parse().unwrap(),
+    fn new(info: &str) -> Result<Self, ParseErrorKind> {
+        let mut info = info.split_whitespace();
+        let limits = info.next().ok_or(ParseErrorKind::MissingTarget)?;
+        let target = info.next().ok_or(ParseErrorKind::MissingTarget)?;
+        let invalid_limits = || ParseErrorKind::InvalidLimits(limits.to_string());
+        let (low, high) = limits.split_once('-').ok_or_else(invalid_limits)?;
+        let low: usize = low.parse().map_err(|_| invalid_limits())?;
+        let high: usize = high.parse().map_err(|_| invalid_limits())?;
+        if low > high {
+            return Err(invalid_limits());
+        }
+        Ok(Self {
+            nums: (low, high),
+            target: target.to_string(),
+        })
+    }
+}
+
+// Counts non-overlapping occurrences of the target made of whole graphemes, as part 2 does.
+fn count_target(password: &str, target: &str) -> usize {
+    let graphemes: Vec<&str> = password.graphemes(true).collect();
+    let target: Vec<&str> = target.graphemes(true).collect();
+    let (mut count, mut start) = (0, 0);
+    while !target.is_empty() && start + target.len() <= graphemes.len() {
+        if graphemes[start..start + target.len()] == target[..] {
+            count += 1;
+            start += target.len();
+        } else {
+            start += 1;
         }
     }
+    count
 }
 
 pub fn policy_pt1(record: &Record) -> bool {
-    let count = record.password.matches(record.constraints.target_letter).count() as u8;
-    return count >= record.constraints.nums.0 && count <= record.constraints.nums.1;
+    let count = count_target(&record.password, &record.constraints.target);
+    count >= record.constraints.nums.0 && count <= record.constraints.nums.1
 }
 
 pub fn policy_pt2(record: &Record) -> bool {
-    let first = record.password.chars().nth(record.constraints.nums.0 as usize - 1).unwrap();
-    let second = record.password.chars().nth(record.constraints.nums.1 as usize - 1).unwrap();
-    let target = record.constraints.target_letter;
-    (first == target && second != target) || (first != target && second == target)
+    match (record.target_at(record.constraints.nums.0), record.target_at(record.constraints.nums.1)) {
+        (Some(first), Some(second)) => first != second,
+        _ => false,
+    }
 }
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -72,50 +144,98 @@ where P: AsRef<Path>, {
 
 #[cfg(test)]
 mod tests {
-    
+
     use super::*;
 
     #[test]
     fn test_password_correctly_instantiated() {
-        let record = Record::new("1-3 a: abcde");
+        let record = Record::new("1-3 a: abcde").unwrap();
         assert_eq!(record.password, "abcde");
-        assert_eq!(record.constraints, Constraints::new("1-3 a"));
+        assert_eq!(record.constraints, Constraints::new("1-3 a").unwrap());
     }
 
     #[test]
     fn test_password_constraints_correctly_instantiated() {
-        let constraints = Constraints::new("1-3 a");
+        let constraints = Constraints::new("1-3 a").unwrap();
         assert_eq!(constraints.nums, (1, 3));
-        assert_eq!(constraints.target_letter, 'a');
+        assert_eq!(constraints.target, "a");
     }
-    
+
+    #[test]
+    fn constraints_accept_wide_limits_and_multi_character_targets() {
+        let constraints = Constraints::new("300-70000 ab").unwrap();
+        assert_eq!(constraints.nums, (300, 70000));
+        assert_eq!(constraints.target, "ab");
+    }
+
+    #[test]
+    fn malformed_records_are_reported_as_parse_errors() {
+        assert_eq!(Record::new("1-3 a abcde"), Err(ParseErrorKind::MissingPassword));
+        assert_eq!(Record::new("1-3: abcde"), Err(ParseErrorKind::MissingTarget));
+        assert_eq!(Record::new("1x3 a: abcde"), Err(ParseErrorKind::InvalidLimits("1x3".to_string())));
+        assert_eq!(Record::new("-1-3 a: abcde"), Err(ParseErrorKind::InvalidLimits("-1-3".to_string())));
+        assert_eq!(Record::new("3-1 a: abcde"), Err(ParseErrorKind::InvalidLimits("3-1".to_string())));
+    }
+
+    #[test]
+    fn unreadable_files_reported() {
+        assert!(matches!(new_records("test_data/missing.txt"), Err(ReadError::Io(_))));
+    }
+
     #[test]
     fn password_validity_correctly_evaluated_for_part_1() {
-        let valid = Record::new("1-3 a: abcde");
+        let valid = Record::new("1-3 a: abcde").unwrap();
         assert!(policy_pt1(&valid));
     }
-    
+
     #[test]
     fn invalid_password_correctly_evaluated_for_part_1() {
-        let invalid = Record::new("1-3 b: cdefg");
+        let invalid = Record::new("1-3 b: cdefg").unwrap();
         assert!(!policy_pt1(&invalid));
     }
 
+    #[test]
+    fn multi_character_target_counted_for_part_1() {
+        assert!(policy_pt1(&Record::new("2-2 ab: abxab").unwrap()));
+        assert!(!policy_pt1(&Record::new("3-4 ab: abxab").unwrap()));
+    }
+
+    #[test]
+    fn targets_inside_combining_sequences_not_counted_for_part_1() {
+        assert!(policy_pt1(&Record::new("1-1 e: e\u{301}ye").unwrap()));
+        assert!(!policy_pt1(&Record::new("1-1 e: e\u{301}y").unwrap()));
+    }
+
     #[test]
     fn can_find_number_of_valid_records_correctly_for_part_1() {
-        let records = new_records("test_data/test1.txt");
+        let records = new_records("test_data/test1.txt").unwrap();
         assert_eq!(find_number_of_valid_records(&records, &policy_pt1), 2);
     }
 
     #[test]
     fn password_validity_correctly_evaluated_for_part_2() {
-        let valid = Record::new("1-3 a: abcde");
+        let valid = Record::new("1-3 a: abcde").unwrap();
         assert!(policy_pt2(&valid));
     }
 
     #[test]
     fn invalid_password_correctly_evaluated_for_part_2() {
-        let invalid = Record::new("1-3 b: cdefg");
+        let invalid = Record::new("1-3 b: cdefg").unwrap();
         assert!(!policy_pt2(&invalid));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn out_of_range_positions_fail_part_2_without_panicking() {
+        assert!(!policy_pt2(&Record::new("0-3 c: abcde").unwrap()));
+        assert!(!policy_pt2(&Record::new("1-6 a: abcde").unwrap()));
+        assert!(!policy_pt2(&Record::new("1-5 de: abcde").unwrap()));
+    }
+
+    #[test]
+    fn positions_count_graphemes_for_part_2() {
+        assert!(policy_pt2(&Record::new("2-3 z: e\u{301}yz").unwrap()));
+        assert!(policy_pt2(&Record::new("1-3 ñ: 🎄añb").unwrap()));
+        assert!(policy_pt2(&Record::new("2-4 bc: abcbx").unwrap()));
+        assert!(!policy_pt2(&Record::new("2-4 bc: abcbc").unwrap()));
+    }
+}
@@ -2,58 +2,163 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
 pub struct Map {
     height: usize,
     width: usize,
-    tree_locations: HashSet<Coord>,
+    trees: Trees,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Storage {
+    // Sparse for maps with very few trees, dense otherwise.
+    Auto,
+    Sparse,
+    Dense,
+}
+
+enum Trees {
+    Sparse(HashSet<Coord>),
+    Dense(BitRows),
+}
+
+// One bit per cell, each row padded to a whole number of words.
+struct BitRows {
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl BitRows {
+    fn new(width: usize) -> Self {
+        BitRows { words_per_row: width.div_ceil(64), words: vec![] }
+    }
+
+    fn push_row(&mut self) {
+        self.words.resize(self.words.len() + self.words_per_row, 0);
+    }
+
+    fn insert(&mut self, x: usize, y: usize) {
+        self.words[y * self.words_per_row + x / 64] |= 1 << (x % 64);
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        self.words[y * self.words_per_row + x / 64] >> (x % 64) & 1 == 1
+    }
+
+    fn coords(&self) -> impl Iterator<Item = Coord> + '_ {
+        self.words.iter().enumerate().flat_map(move |(index, &word)| {
+            let (y, first_x) = (index / self.words_per_row, index % self.words_per_row * 64);
+            (0..64).filter(move |bit| word >> bit & 1 == 1).map(move |bit| Coord::new(first_x + bit, y))
+        })
+    }
 }
 
 impl Map {
     pub fn new(filepath: &str) -> Self {
-        let mut tree_locations: HashSet<Coord> = HashSet::new();
-        let mut y = 0;
-        let mut x = 0;
-        if let Ok(lines) = read_lines(filepath) {
-            for line in lines {
-                if let Ok(info) = line {
-                    x = 0;
-                    for symbol in info.chars() {
-                        if symbol == '#' {
-                            tree_locations.insert(Coord::new(x, y));
-                        }
-                        x += 1;
-                    }
-                    y += 1;
-                }
+        Map::with_storage(filepath, Storage::Auto)
+    }
+
+    pub fn with_storage(filepath: &str, storage: Storage) -> Self {
+        let lines = read_lines(filepath).into_iter().flatten().map_while(Result::ok);
+        Map::from_rows(lines, storage)
+    }
+
+    // The width is taken from the first row; anything past it in later rows is ignored.
+    // Sparse maps keep only the trees. Otherwise the rows are packed as they are read, and
+    // an automatic choice unpacks the trees afterwards if there turn out to be very few.
+    pub fn from_rows<I, S>(rows: I, storage: Storage) -> Self
+    where I: IntoIterator<Item = S>, S: AsRef<str>, {
+        let mut rows = rows.into_iter().peekable();
+        let width = rows.peek().map_or(0, |row| row.as_ref().chars().count());
+        let mut dense = BitRows::new(width);
+        let mut sparse = HashSet::new();
+        let mut tree_count = 0;
+        let mut height = 0;
+        for row in rows {
+            let trees = row.as_ref().chars().take(width).enumerate()
+                .filter(|&(_, symbol)| symbol == '#')
+                .map(|(x, _)| x);
+            if storage == Storage::Sparse {
+                sparse.extend(trees.map(|x| Coord::new(x, height)));
+            } else {
+                dense.push_row();
+                trees.for_each(|x| {
+                    dense.insert(x, height);
+                    tree_count += 1;
+                });
             }
+            height += 1;
         }
-        Map {
-            height: y,
-            width: x,
-            tree_locations,
+        let trees = match storage {
+            Storage::Sparse => Trees::Sparse(sparse),
+            Storage::Auto if tree_count * 256 < width * height => Trees::Sparse(dense.coords().collect()),
+            _ => Trees::Dense(dense),
+        };
+        Map { height, width, trees }
+    }
+
+    pub fn storage(&self) -> Storage {
+        match self.trees {
+            Trees::Sparse(_) => Storage::Sparse,
+            Trees::Dense(_) => Storage::Dense,
         }
     }
 
-    pub fn find_number_of_trees_in_journey(&self, slope: (usize, usize)) -> usize {
-        let mut count = 0;
-        let mut toboggan = Coord::new(0, 0);
-        while toboggan.y < self.height {
-            toboggan = self.translate(toboggan, slope);
-            if self.tree_locations.contains(&toboggan) {
-                count += 1;
-            }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn is_tree(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        match &self.trees {
+            Trees::Sparse(tree_locations) => tree_locations.contains(&Coord::new(x, y)),
+            Trees::Dense(rows) => rows.contains(x, y),
         }
-        count
     }
 
-    fn translate(&self, toboggan: Coord, slope: (usize, usize)) -> Coord {
-        Coord::new(
-            (toboggan.x + slope.0) % self.width,
-            toboggan.y + slope.1
-        )
+    // Cells visited after leaving the top-left corner, wrapping horizontally only.
+    pub fn path(&self, slope: Slope) -> Journey<'_> {
+        Journey::new(self, slope, false)
     }
- }
+
+    // Cells visited after leaving the top-left corner, wrapping in both directions
+    // until the toboggan arrives back where it started.
+    pub fn wrapping_path(&self, slope: Slope) -> Journey<'_> {
+        Journey::new(self, slope, true)
+    }
+
+    pub fn find_number_of_trees_in_journey(&self, slope: Slope) -> usize {
+        self.path(slope).filter(|cell| cell.is_tree).count()
+    }
+
+    // Every slope with steps inside the given bounds that actually heads down the map.
+    // Slopes with a down step below 1 never get past the first row, so they are left out
+    // rather than crowding the fewest trees with zeroes.
+    pub fn rank_slopes_within(&self, rights: RangeInclusive<isize>, downs: RangeInclusive<isize>) -> SlopeRanking {
+        let downs = (*downs.start()).max(1)..=*downs.end();
+        let slopes: Vec<Slope> = rights
+            .flat_map(|right| downs.clone().map(move |down| Slope::new(right, down)))
+            .collect();
+        self.rank_slopes(&slopes)
+    }
+
+    pub fn rank_slopes(&self, slopes: &[Slope]) -> SlopeRanking {
+        let mut ranked: Vec<(Slope, usize)> = slopes.iter()
+            .map(|&slope| (slope, self.find_number_of_trees_in_journey(slope)))
+            .collect();
+        ranked.sort_by_key(|&(slope, trees)| (trees, slope.right, slope.down));
+        ranked.dedup();
+        SlopeRanking { ranked }
+    }
+}
 
 #[derive(Hash, PartialEq, Eq)]
 struct Coord {
@@ -67,6 +172,138 @@ impl Coord {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Slope {
+    pub right: isize,
+    pub down: isize,
+}
+
+impl Slope {
+    pub fn new(right: isize, down: isize) -> Self {
+        Slope { right, down }
+    }
+
+    // A slope of `numerator / denominator` columns per row, stepping to the next cell
+    // that lies exactly on the line. None if the steps, once reduced and with the sign
+    // moved onto the numerator, do not fit in an `isize`.
+    pub fn rational(numerator: isize, denominator: isize) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs());
+        let right = numerator.unsigned_abs() / divisor;
+        let right = if (numerator < 0) != (denominator < 0) {
+            0isize.checked_sub_unsigned(right)?
+        } else {
+            isize::try_from(right).ok()?
+        };
+        let down = isize::try_from(denominator.unsigned_abs() / divisor).ok()?;
+        Some(Slope::new(right, down))
+    }
+}
+
+impl FromStr for Slope {
+    type Err = String;
+
+    // Parses steps as "right,down", e.g. "3,1" or "-1,2", or a rational slope of columns
+    // per row as "numerator/denominator", e.g. "1/2".
+    fn from_str(info: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid slope \"{}\"", info);
+        if let Some((numerator, denominator)) = info.split_once('/') {
+            return match (numerator.trim().parse(), denominator.trim().parse()) {
+                (Ok(numerator), Ok(denominator)) => Slope::rational(numerator, denominator).ok_or_else(invalid),
+                _ => Err(invalid()),
+            };
+        }
+        let (right, down) = info.split_once(',').ok_or_else(invalid)?;
+        match (right.trim().parse(), down.trim().parse()) {
+            (Ok(right), Ok(down)) => Ok(Slope::new(right, down)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Slopes ordered from fewest to most trees hit, ties broken by the steps themselves.
+pub struct SlopeRanking {
+    pub ranked: Vec<(Slope, usize)>,
+}
+
+impl SlopeRanking {
+    pub fn fewest(&self) -> Vec<(Slope, usize)> {
+        self.ranked.first().map_or(vec![], |&(_, trees)| self.with_trees(trees))
+    }
+
+    pub fn most(&self) -> Vec<(Slope, usize)> {
+        self.ranked.last().map_or(vec![], |&(_, trees)| self.with_trees(trees))
+    }
+
+    // Groups of slopes that hit the same number of trees, in ascending order of trees.
+    pub fn ties(&self) -> Vec<(usize, Vec<Slope>)> {
+        self.ranked
+            .chunk_by(|a, b| a.1 == b.1)
+            .filter(|group| group.len() > 1)
+            .map(|group| (group[0].1, group.iter().map(|&(slope, _)| slope).collect()))
+            .collect()
+    }
+
+    fn with_trees(&self, trees: usize) -> Vec<(Slope, usize)> {
+        self.ranked.iter().filter(|&&(_, count)| count == trees).copied().collect()
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cell {
+    pub x: usize,
+    pub y: usize,
+    pub is_tree: bool,
+}
+
+pub struct Journey<'a> {
+    map: &'a Map,
+    slope: Slope,
+    wraps_vertically: bool,
+    position: Option<(usize, usize)>,
+}
+
+impl<'a> Journey<'a> {
+    fn new(map: &'a Map, slope: Slope, wraps_vertically: bool) -> Self {
+        let position = if map.width == 0 || map.height == 0 { None } else { Some((0, 0)) };
+        Journey { map, slope, wraps_vertically, position }
+    }
+}
+
+impl Iterator for Journey<'_> {
+    type Item = Cell;
+
+    fn next(&mut self) -> Option<Cell> {
+        let (x, y) = self.position?;
+        let (width, height) = (self.map.width, self.map.height);
+        // Steps are reduced to within the map first so that huge ones cannot overflow.
+        let x = (x + self.slope.right.rem_euclid(width as isize) as usize) % width;
+        let y = if self.wraps_vertically {
+            (y + self.slope.down.rem_euclid(height as isize) as usize) % height
+        } else {
+            match y.checked_add_signed(self.slope.down) {
+                Some(y) if y < height => y,
+                _ => {
+                    self.position = None;
+                    return None;
+                },
+            }
+        };
+        if (x, y) == (0, 0) {
+            self.position = None;
+            return None;
+        }
+        self.position = Some((x, y));
+        Some(Cell { x, y, is_tree: self.map.is_tree(x, y) })
+    }
+}
+
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where P: AsRef<Path>, {
@@ -75,11 +312,18 @@ where P: AsRef<Path>, {
 }
 
 pub fn part1(map: &Map) -> usize {
-    map.find_number_of_trees_in_journey((3,1))
+    map.find_number_of_trees_in_journey(Slope::new(3, 1))
 }
 
-pub fn part2(map: &Map) -> usize {
-    let slopes = [(3, 1), (1, 1), (5, 1), (7, 1), (1, 2)];
+pub const PART2_SLOPES: [Slope; 5] = [
+    Slope { right: 3, down: 1 },
+    Slope { right: 1, down: 1 },
+    Slope { right: 5, down: 1 },
+    Slope { right: 7, down: 1 },
+    Slope { right: 1, down: 2 },
+];
+
+pub fn part2(map: &Map, slopes: &[Slope]) -> usize {
     slopes.iter().fold(1, |product, &slope| product * map.find_number_of_trees_in_journey(slope))
 }
 
@@ -93,9 +337,10 @@ mod tests {
         let map = Map::new("test_data/test1.txt");
         assert_eq!(map.height, 11);
         assert_eq!(map.width, 11);
-        assert!(map.tree_locations.contains(&Coord::new(2, 0)));
-        assert!(map.tree_locations.contains(&Coord::new(0, 1)));
-        assert!(map.tree_locations.contains(&Coord::new(10, 10)));
+        assert!(map.is_tree(2, 0));
+        assert!(map.is_tree(0, 1));
+        assert!(map.is_tree(10, 10));
+        assert!(!map.is_tree(0, 0));
     }
 
     #[test]
@@ -108,15 +353,189 @@ mod tests {
     #[test]
     fn trees_encountered_in_journey_correctly_counted () {
         let map = Map::new("test_data/test1.txt");
-        assert_eq!(map.find_number_of_trees_in_journey((3, 1)), 7);
+        assert_eq!(map.find_number_of_trees_in_journey(Slope::new(3, 1)), 7);
     }
 
     #[test]
     fn trees_corretly_counted_for_another_slope() {
         let map = Map::new("test_data/test1.txt");
-        assert_eq!(map.find_number_of_trees_in_journey((1, 1)), 2);
-        assert_eq!(map.find_number_of_trees_in_journey((5, 1)), 3);
-        assert_eq!(map.find_number_of_trees_in_journey((7, 1)), 4);
-        assert_eq!(map.find_number_of_trees_in_journey((1, 2)), 2);
+        assert_eq!(map.find_number_of_trees_in_journey(Slope::new(1, 1)), 2);
+        assert_eq!(map.find_number_of_trees_in_journey(Slope::new(5, 1)), 3);
+        assert_eq!(map.find_number_of_trees_in_journey(Slope::new(7, 1)), 4);
+        assert_eq!(map.find_number_of_trees_in_journey(Slope::new(1, 2)), 2);
+    }
+
+    #[test]
+    fn rational_slopes_reduced_to_smallest_step() {
+        assert_eq!(Slope::rational(2, 4), Some(Slope::new(1, 2)));
+        assert_eq!(Slope::rational(3, -6), Some(Slope::new(-1, 2)));
+        assert_eq!(Slope::rational(0, 5), Some(Slope::new(0, 1)));
+        assert_eq!(Slope::rational(1, 0), None);
+        assert_eq!(Slope::rational(1, isize::MIN), None);
+        assert_eq!(Slope::rational(2, isize::MIN), Some(Slope::new(-1, -(isize::MIN / 2))));
+        assert_eq!(Slope::rational(isize::MIN, 1), Some(Slope::new(isize::MIN, 1)));
+        assert_eq!(Slope::rational(isize::MIN, -1), None);
+    }
+
+    #[test]
+    fn journey_yields_visited_cells_with_trees() {
+        let map = Map::new("test_data/test1.txt");
+        let cells: Vec<Cell> = map.path(Slope::new(3, 1)).take(2).collect();
+        assert_eq!(cells, vec![
+            Cell { x: 3, y: 1, is_tree: false },
+            Cell { x: 6, y: 2, is_tree: true },
+        ]);
+        assert_eq!(map.path(Slope::new(3, 1)).filter(|cell| !cell.is_tree).count(), 3);
+        assert_eq!(map.path(Slope::new(3, 1)).find(|cell| cell.is_tree), Some(Cell { x: 6, y: 2, is_tree: true }));
+    }
+
+    #[test]
+    fn journey_can_head_left() {
+        let map = Map::new("test_data/test1.txt");
+        let first = map.path(Slope::new(-1, 1)).next();
+        assert_eq!(first, Some(Cell { x: 10, y: 1, is_tree: false }));
+        assert_eq!(map.path(Slope::new(-3, 1)).count(), 10);
+    }
+
+    #[test]
+    fn journey_stops_when_leaving_the_top_of_the_map() {
+        let map = Map::new("test_data/test1.txt");
+        assert_eq!(map.path(Slope::new(1, -1)).count(), 0);
+    }
+
+    #[test]
+    fn huge_steps_wrap_without_overflowing() {
+        let map = Map::new("test_data/test1.txt");
+        let first = map.path(Slope::new(isize::MAX, 1)).next();
+        assert_eq!(first, Some(Cell { x: (isize::MAX % 11) as usize, y: 1, is_tree: false }));
+        assert_eq!(map.path(Slope::new(isize::MAX, 1)).count(), 10);
+        assert_eq!(map.path(Slope::new(isize::MIN, 1)).count(), 10);
+        assert_eq!(map.path(Slope::new(1, isize::MAX)).count(), 0);
+        assert_eq!(map.path(Slope::new(1, isize::MIN)).count(), 0);
+        assert_eq!(map.wrapping_path(Slope::new(isize::MIN, isize::MAX)).count(), 10);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn wrapping_journey_ends_back_at_the_start() {
+        let map = Map::new("test_data/test1.txt");
+        assert_eq!(map.wrapping_path(Slope::new(3, 1)).count(), 10);
+        assert_eq!(map.wrapping_path(Slope::new(1, -1)).next(), Some(Cell { x: 1, y: 10, is_tree: true }));
+        assert_eq!(map.wrapping_path(Slope::new(1, 0)).count(), 10);
+    }
+
+    #[test]
+    fn part2_multiplies_trees_for_the_given_slopes() {
+        let map = Map::new("test_data/test1.txt");
+        assert_eq!(part2(&map, &PART2_SLOPES), 336);
+        assert_eq!(part2(&map, &[Slope::new(1, 1), Slope::new(1, 2)]), 4);
+    }
+
+    #[test]
+    fn slopes_parsed_from_strings() {
+        assert_eq!("3,1".parse::<Slope>(), Ok(Slope::new(3, 1)));
+        assert_eq!(" -1, 2".parse::<Slope>(), Ok(Slope::new(-1, 2)));
+        assert!("3".parse::<Slope>().is_err());
+        assert!("a,1".parse::<Slope>().is_err());
+        assert_eq!("2/4".parse::<Slope>(), Ok(Slope::new(1, 2)));
+        assert!("1/0".parse::<Slope>().is_err());
+    }
+
+    #[test]
+    fn given_slopes_ranked_by_trees_hit() {
+        let map = Map::new("test_data/test1.txt");
+        let ranking = map.rank_slopes(&PART2_SLOPES);
+        assert_eq!(ranking.fewest(), vec![(Slope::new(1, 1), 2), (Slope::new(1, 2), 2)]);
+        assert_eq!(ranking.most(), vec![(Slope::new(3, 1), 7)]);
+        assert_eq!(ranking.ties(), vec![(2, vec![Slope::new(1, 1), Slope::new(1, 2)])]);
+    }
+
+    #[test]
+    fn slopes_within_bounds_searched_and_ranked() {
+        let map = Map::new("test_data/test1.txt");
+        let ranking = map.rank_slopes_within(0..=7, 1..=2);
+        assert_eq!(ranking.ranked.len(), 16);
+        assert_eq!(ranking.most(), vec![(Slope::new(3, 1), 7)]);
+        assert!(ranking.ranked.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert_eq!(map.rank_slopes_within(0..=0, 0..=0).ranked, vec![]);
+    }
+
+    #[test]
+    fn slopes_that_never_head_down_left_out_of_the_search() {
+        let map = Map::new("test_data/test1.txt");
+        let ranking = map.rank_slopes_within(-1..=1, -2..=1);
+        assert_eq!(ranking.ranked.len(), 3);
+        assert!(ranking.ranked.iter().all(|&(slope, _)| slope.down == 1));
+        assert_eq!(ranking.fewest(), vec![(Slope::new(1, 1), 2)]);
+    }
+
+    #[test]
+    fn storage_chosen_automatically_or_explicitly() {
+        assert_eq!(Map::new("test_data/test1.txt").storage(), Storage::Dense);
+        assert_eq!(Map::with_storage("test_data/test1.txt", Storage::Sparse).storage(), Storage::Sparse);
+        let mut rows = vec![".".repeat(300); 300];
+        rows[7] = format!("{}#{}", ".".repeat(5), ".".repeat(294));
+        let map = Map::from_rows(&rows, Storage::Auto);
+        assert_eq!(map.storage(), Storage::Sparse);
+        assert!(map.is_tree(5, 7));
+    }
+
+    #[test]
+    fn sparse_and_dense_maps_answer_queries_identically() {
+        let sparse = Map::with_storage("test_data/input.txt", Storage::Sparse);
+        let dense = Map::with_storage("test_data/input.txt", Storage::Dense);
+        assert_eq!((sparse.width(), sparse.height()), (dense.width(), dense.height()));
+        for y in 0..dense.height() {
+            for x in 0..dense.width() {
+                assert_eq!(sparse.is_tree(x, y), dense.is_tree(x, y));
+            }
+        }
+        assert_eq!(part2(&sparse, &PART2_SLOPES), part2(&dense, &PART2_SLOPES));
+    }
+
+    #[test]
+    fn rows_wider_than_a_word_packed_correctly() {
+        let row = format!("{}#{}#", ".".repeat(63), ".".repeat(70));
+        let map = Map::from_rows([row.as_str(), row.as_str()], Storage::Dense);
+        assert_eq!(map.width(), 135);
+        assert!(map.is_tree(63, 1) && map.is_tree(134, 1));
+        assert!(!map.is_tree(64, 1) && !map.is_tree(135, 1));
+    }
+
+    // Rows of pseudo-random cells, roughly a quarter of them trees.
+    fn random_rows(width: usize, height: usize) -> Vec<String> {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        (0..height).map(|_| (0..width).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            if seed.is_multiple_of(4) { '#' } else { '.' }
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn large_sparse_and_dense_maps_rank_slopes_identically() {
+        let rows = random_rows(1_000, 2_000);
+        let sparse = Map::from_rows(&rows, Storage::Sparse);
+        let dense = Map::from_rows(&rows, Storage::Dense);
+        assert_eq!(Map::from_rows(&rows, Storage::Auto).storage(), Storage::Dense);
+        let ranked = |map: &Map| map.rank_slopes_within(-3..=3, 1..=3).ranked;
+        assert_eq!(ranked(&sparse), ranked(&dense));
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn benchmark_sparse_against_dense_storage() {
+        use std::time::Instant;
+        let rows = random_rows(1_000, 5_000);
+        for storage in [Storage::Sparse, Storage::Dense] {
+            let start = Instant::now();
+            let map = Map::from_rows(&rows, storage);
+            let loaded = start.elapsed();
+            let start = Instant::now();
+            let ranking = map.rank_slopes_within(-3..=3, 1..=3);
+            let trees: usize = ranking.ranked.iter().map(|&(_, trees)| trees).sum();
+            println!("{:?}: load {:?}, {} journeys {:?} ({} trees)", storage, loaded, ranking.ranked.len(), start.elapsed(), trees);
+        }
+    }
+}
@@ -1,10 +1,65 @@
 mod forrest;
-use forrest::Map;
+use std::env;
+use forrest::{Map, Slope, Storage, PART2_SLOPES};
 use forrest::part1;
 use forrest::part2;
 
+// Usage: day_3 [--sparse | --dense] [--wrap | --rank <max step>] [<right>,<down> | <numerator>/<denominator> ...]
 fn main() {
-    let map = Map::new("test_data/input.txt");
+    let mut args = env::args().skip(1);
+    let mut storage = None;
+    let mut wrap = false;
+    let mut rank = None;
+    let mut slopes = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sparse" => storage = Some(Storage::Sparse),
+            "--dense" => storage = Some(Storage::Dense),
+            "--wrap" => wrap = true,
+            "--rank" => match args.next().and_then(|step| step.parse::<isize>().ok()) {
+                Some(step) => rank = Some(step),
+                None => {
+                    eprintln!("--rank needs the largest step to try");
+                    return;
+                }
+            },
+            _ => match arg.parse::<Slope>() {
+                Ok(slope) => slopes.push(slope),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    return;
+                }
+            },
+        }
+    }
+    let map = match storage {
+        Some(storage) => Map::with_storage("test_data/input.txt", storage),
+        None => Map::new("test_data/input.txt"),
+    };
+    if storage.is_some() {
+        println!("map is {}x{} with {:?} storage", map.width(), map.height(), map.storage());
+    }
+    if let Some(step) = rank {
+        let ranking = map.rank_slopes_within(-step..=step, 1..=step);
+        let describe = |slopes: Vec<(Slope, usize)>| slopes.iter()
+            .map(|(slope, _)| format!("{},{}", slope.right, slope.down))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let (Some(&(_, fewest)), Some(&(_, most))) = (ranking.ranked.first(), ranking.ranked.last()) {
+            println!("fewest trees ({}): {}", fewest, describe(ranking.fewest()));
+            println!("most trees ({}): {}", most, describe(ranking.most()));
+        }
+        println!("{} tree counts shared by more than one slope", ranking.ties().len());
+        return;
+    }
+    let slopes = if slopes.is_empty() { PART2_SLOPES.to_vec() } else { slopes };
+    if wrap {
+        for slope in slopes {
+            let trees = map.wrapping_path(slope).filter(|cell| cell.is_tree).count();
+            println!("slope {},{} hits {} trees wrapping both ways", slope.right, slope.down, trees);
+        }
+        return;
+    }
     println!("part 1 solution is {}", part1(&map));
-    println!("part 2 solution is {}", part2(&map));
+    println!("part 2 solution is {}", part2(&map, &slopes));
 }
@@ -1,14 +1,40 @@
+mod passport_export;
 mod passport_processing;
+mod passport_schema;
 use passport_processing::{
     create_records_from_path,
     find_number_of_possible_passports,
-    find_number_of_valid_passports
+    find_number_of_valid_passports,
+    find_reports_of_passports
 };
+use passport_schema::Schema;
+use std::env;
 use std::io;
 
+// Usage: day_4 [--report | --json | --csv] [schema.toml]
 fn main() -> Result<(), io::Error> {
-    let records = create_records_from_path("test_data/input.txt")?;
-    println!("The number of possible passports is {}", find_number_of_possible_passports(&records));
-    println!("The number of valid passports is {}", find_number_of_valid_passports(&records));
+    let args: Vec<String> = env::args().skip(1).collect();
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    let (records, skipped) = create_records_from_path("test_data/input.txt")?;
+    for error in skipped {
+        eprintln!("skipped record: {}", error);
+    }
+    let schema = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(filepath) => Schema::from_path(filepath).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+        None => Schema::passport(),
+    };
+    if has_flag("--json") {
+        return passport_export::write_json_lines(&records, &schema, &mut io::stdout().lock());
+    }
+    if has_flag("--csv") {
+        return passport_export::write_csv(&records, &schema, &mut io::stdout().lock());
+    }
+    if has_flag("--report") {
+        for (index, report) in find_reports_of_passports(&records, &schema).iter().enumerate() {
+            println!("record {}: {}", index + 1, report);
+        }
+    }
+    println!("The number of possible passports is {}", find_number_of_possible_passports(&records, &schema));
+    println!("The number of valid passports is {}", find_number_of_valid_passports(&records, &schema));
     Ok(())
 }
@@ -0,0 +1,190 @@
+use std::io::{self, Write};
+use crate::passport_processing::Record;
+use crate::passport_schema::{Normalise, Rule, Schema};
+
+#[derive(PartialEq, Debug)]
+pub enum Cell {
+    Empty,
+    Integer(i64),
+    Decimal(f64),
+    Text(String),
+    Flag(bool),
+}
+
+impl Cell {
+    fn to_json(&self) -> String {
+        match self {
+            Cell::Empty => "null".to_string(),
+            Cell::Integer(num) => num.to_string(),
+            Cell::Decimal(num) => num.to_string(),
+            Cell::Text(text) => json_string(text),
+            Cell::Flag(flag) => flag.to_string(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        match self {
+            Cell::Empty => String::new(),
+            Cell::Integer(num) => num.to_string(),
+            Cell::Decimal(num) => num.to_string(),
+            Cell::Text(text) => csv_field(text),
+            Cell::Flag(flag) => flag.to_string(),
+        }
+    }
+}
+
+const CENTIMETRES_PER_UNIT: [(&str, f64); 4] = [("mm", 0.1), ("cm", 1.0), ("in", 2.54), ("m", 100.0)];
+
+pub fn normalise(value: &str, normalise: Normalise, rule: &Rule) -> Cell {
+    let cleaned = match normalise {
+        Normalise::Keep => None,
+        Normalise::Integer => value.trim().parse().ok().map(Cell::Integer),
+        Normalise::Lowercase => Some(Cell::Text(value.trim().to_lowercase())),
+        Normalise::Centimetres => to_centimetres(value.trim(), rule).map(Cell::Decimal),
+    };
+    cleaned.unwrap_or(Cell::Text(value.to_string()))
+}
+
+// Only the units the field's rule declares are converted, so a value in any other unit,
+// or in any unit at all for a field without a `units` rule, is kept as it is.
+fn to_centimetres(value: &str, rule: &Rule) -> Option<f64> {
+    let declared = match rule {
+        Rule::UnitRange(units) => units,
+        _ => return None,
+    };
+    declared.iter().find_map(|(unit, _)| {
+        let &(_, factor) = CENTIMETRES_PER_UNIT.iter().find(|&&(known, _)| known == unit)?;
+        let num: f64 = value.strip_suffix(unit.as_str())?.trim().parse().ok().filter(|num: &f64| num.is_finite())?;
+        Some((num * factor * 100.0).round() / 100.0)
+    })
+}
+
+// The schema's fields in declaration order followed by `valid`; unknown fields are dropped.
+pub fn columns(schema: &Schema) -> Vec<String> {
+    schema.fields().iter().map(|field| field.name.clone()).chain(["valid".to_string()]).collect()
+}
+
+pub fn row(record: &Record, schema: &Schema) -> Vec<Cell> {
+    schema.fields().iter()
+        .map(|field| record.get(&field.name).map_or(Cell::Empty, |value| normalise(value, field.normalise, &field.rule)))
+        .chain([Cell::Flag(record.validate(schema).is_valid())])
+        .collect()
+}
+
+pub fn write_json_lines<W: Write>(records: &[Record], schema: &Schema, writer: &mut W) -> io::Result<()> {
+    let columns = columns(schema);
+    for record in records {
+        let pairs: Vec<String> = columns.iter().zip(row(record, schema))
+            .map(|(column, cell)| format!("{}:{}", json_string(column), cell.to_json()))
+            .collect();
+        writeln!(writer, "{{{}}}", pairs.join(","))?;
+    }
+    Ok(())
+}
+
+pub fn write_csv<W: Write>(records: &[Record], schema: &Schema, writer: &mut W) -> io::Result<()> {
+    let header: Vec<String> = columns(schema).iter().map(|column| csv_field(column)).collect();
+    writeln!(writer, "{}", header.join(","))?;
+    for record in records {
+        let cells: Vec<String> = row(record, schema).iter().map(|cell| cell.to_csv()).collect();
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    Ok(())
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for symbol in text.chars() {
+        match symbol {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            symbol if symbol.is_control() => escaped.push_str(&format!("\\u{:04x}", symbol as u32)),
+            symbol => escaped.push(symbol),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::passport_processing::{create_records_from_path, new_record};
+
+    #[test]
+    fn values_normalised_by_field() {
+        let height = Rule::UnitRange(vec![("cm".to_string(), 150..=193), ("in".to_string(), 59..=76)]);
+        assert_eq!(normalise("1937", Normalise::Integer, &Rule::Any), Cell::Integer(1937));
+        assert_eq!(normalise("19x7", Normalise::Integer, &Rule::Any), Cell::Text("19x7".to_string()));
+        assert_eq!(normalise("183cm", Normalise::Centimetres, &height), Cell::Decimal(183.0));
+        assert_eq!(normalise("61in", Normalise::Centimetres, &height), Cell::Decimal(154.94));
+        assert_eq!(normalise("183", Normalise::Centimetres, &height), Cell::Text("183".to_string()));
+        assert_eq!(normalise("#FFFFFD", Normalise::Lowercase, &Rule::Any), Cell::Text("#fffffd".to_string()));
+        assert_eq!(normalise("AbC", Normalise::Keep, &Rule::Any), Cell::Text("AbC".to_string()));
+    }
+
+    #[test]
+    fn only_units_declared_by_the_field_converted() {
+        let height = Rule::UnitRange(vec![("cm".to_string(), 150..=193), ("in".to_string(), 59..=76)]);
+        assert_eq!(normalise("1.8m", Normalise::Centimetres, &height), Cell::Text("1.8m".to_string()));
+        assert_eq!(normalise("1800mm", Normalise::Centimetres, &height), Cell::Text("1800mm".to_string()));
+        assert_eq!(normalise("183cm", Normalise::Centimetres, &Rule::Any), Cell::Text("183cm".to_string()));
+        let metric = Rule::UnitRange(vec![("m".to_string(), 1..=2), ("mm".to_string(), 1500..=1930)]);
+        assert_eq!(normalise("1.8m", Normalise::Centimetres, &metric), Cell::Decimal(180.0));
+        assert_eq!(normalise("1800mm", Normalise::Centimetres, &metric), Cell::Decimal(180.0));
+        let unknown = Rule::UnitRange(vec![("ft".to_string(), 4..=7)]);
+        assert_eq!(normalise("6ft", Normalise::Centimetres, &unknown), Cell::Text("6ft".to_string()));
+    }
+
+    #[test]
+    fn records_exported_as_json_lines() -> Result<(), io::Error> {
+        let (records, _) = create_records_from_path("test_data/test1.txt")?;
+        let mut output = vec![];
+        write_json_lines(&records[..2], &Schema::passport(), &mut output)?;
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "{\"byr\":1937,\"iyr\":2017,\"eyr\":2020,\"hgt\":183,\"hcl\":\"#fffffd\",\"ecl\":\"gry\",\"pid\":\"860033327\",\"cid\":\"147\",\"valid\":true}\n",
+            "{\"byr\":1929,\"iyr\":2013,\"eyr\":2023,\"hgt\":null,\"hcl\":\"#cfa07d\",\"ecl\":\"amb\",\"pid\":\"028048884\",\"cid\":\"350\",\"valid\":false}\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn records_exported_as_csv() -> Result<(), io::Error> {
+        let (records, _) = create_records_from_path("test_data/test1.txt")?;
+        let mut output = vec![];
+        write_csv(&records[2..], &Schema::passport(), &mut output)?;
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "byr,iyr,eyr,hgt,hcl,ecl,pid,cid,valid\n",
+            "1931,2013,2024,179,#ae17e1,brn,760753108,,true\n",
+            ",2011,2025,149.86,#cfa07d,brn,166559648,,false\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_fields_left_out_without_marking_the_record_invalid() {
+        let record = new_record("iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719 zzz:1").unwrap();
+        let mut output = vec![];
+        write_csv(&[record], &Schema::passport(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().lines().nth(1), Some("1944,2010,2021,158,#b6652a,blu,093154719,,true"));
+    }
+
+    #[test]
+    fn text_escaped_for_each_format() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -1,131 +1,273 @@
-use std::fs::read_to_string;
-use std::io;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 use std::collections::{HashMap};
-use regex::Regex;
+use std::fmt;
+use std::iter;
+use crate::passport_schema::{Schema, Violation};
 
 #[derive(PartialEq, Eq)]
 pub struct Record {
     fields: HashMap<String, String>
 }
 
+impl Record {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|value| value.as_str())
+    }
 
+    fn has_required_fields(&self, schema: &Schema) -> bool {
+        schema.fields().iter()
+            .filter(|field| field.required)
+            .all(|field| self.fields.contains_key(&field.name))
+    }
 
-impl Record {
-    // make map of functions available like a constant
-
-    fn fields_are_valid(&self) -> bool {
-        let mut validators = HashMap::<String, &dyn Fn(&str)->bool>::new();
-        validators.insert("byr".to_string(), &is_byr_valid);
-        validators.insert("iyr".to_string(), &is_iyr_valid);
-        validators.insert("eyr".to_string(), &is_eyr_valid);
-        validators.insert("hgt".to_string(), &is_hgt_valid);
-        validators.insert("hcl".to_string(), &is_hcl_valid);
-        validators.insert("ecl".to_string(), &is_ecl_valid);
-        validators.insert("pid".to_string(), &is_pid_valid);
-
-        for field in validators.keys() {
-            let value = &self.fields[field];
-            let func = validators[field];
-            if func(value) == false {
-                return false
+    fn fields_are_valid(&self, schema: &Schema) -> bool {
+        self.validate(schema).is_valid()
+    }
+
+    pub fn validate(&self, schema: &Schema) -> Report {
+        let mut issues = vec![];
+        for field in schema.fields() {
+            match self.fields.get(&field.name) {
+                None if field.required => issues.push(Issue::Missing(field.name.clone())),
+                None => (),
+                Some(value) => match field.rule.check(value) {
+                    Ok(()) => (),
+                    Err(Violation::Malformed(reason)) => issues.push(Issue::Malformed { field: field.name.clone(), value: value.clone(), reason }),
+                    Err(Violation::OutOfRange(reason)) => issues.push(Issue::OutOfRange { field: field.name.clone(), value: value.clone(), reason }),
+                },
             }
         }
-        true
+        let mut unknown: Vec<(&String, &String)> = self.fields.iter()
+            .filter(|(name, _)| schema.field(name).is_none())
+            .collect();
+        unknown.sort();
+        issues.extend(unknown.into_iter().map(|(field, value)| Issue::Unknown { field: field.clone(), value: value.clone() }));
+        Report { issues }
     }
 }
 
-fn new_record(info: &str) -> Record{
-    let mut fields: HashMap<String, String> = HashMap::new();
-    let attributes = info.split(&[' ', '\n'][..]);
-    for attribute in attributes {
-        let key_and_value: Vec<&str> = attribute.split(":").collect();
-        fields.insert(key_and_value[0].to_string(), key_and_value[1].to_string());
+#[derive(PartialEq, Eq, Debug)]
+pub enum Issue {
+    Missing(String),
+    Malformed { field: String, value: String, reason: String },
+    OutOfRange { field: String, value: String, reason: String },
+    Unknown { field: String, value: String },
+}
+
+impl Issue {
+    // Unknown fields are only reported; they do not make a passport invalid.
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Issue::Unknown { .. })
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Missing(field) => write!(f, "{} is missing", field),
+            Issue::Malformed { field, value, reason } => write!(f, "{}:{} is malformed: {}", field, value, reason),
+            Issue::OutOfRange { field, value, reason } => write!(f, "{}:{} is out of range: {}", field, value, reason),
+            Issue::Unknown { field, value } => write!(f, "{}:{} is not a known field", field, value),
+        }
     }
-    Record {fields}
 }
 
+#[derive(PartialEq, Eq, Debug)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        !self.issues.iter().any(|issue| issue.is_failure())
+    }
+}
 
-// COULD BE REFACTORED HERE
-fn is_passport(record: &Record) -> bool {
-    let passport_fields = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
-    for field in passport_fields {
-        if record.fields.get(field) == None {
-            return false;
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.is_valid() { "valid" } else { "invalid" })?;
+        if self.issues.is_empty() {
+            return Ok(());
         }
+        let issues: Vec<String> = self.issues.iter().map(|issue| issue.to_string()).collect();
+        write!(f, ": {}", issues.join("; "))
     }
-    true
 }
 
-pub fn create_records_from_path(filepath: &str) -> Result<Vec<Record>, io::Error>{
-    let raw_data = read_to_string(filepath)?;
-    let data: Vec<&str> = raw_data.split("\n\n").collect();
-    let mut records: Vec<Record> = Vec::new();
-    for info in data {
-        records.push(new_record(info));
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ParseErrorKind {
+    MissingColon(String),
+    EmptyKey(String),
+    DuplicateKey(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::MissingColon(token) => write!(f, "expected key:value, found \"{}\"", token),
+            ParseErrorKind::EmptyKey(token) => write!(f, "missing key in \"{}\"", token),
+            ParseErrorKind::DuplicateKey(key) => write!(f, "duplicate key \"{}\"", key),
+        }
     }
-    Ok(records)
 }
 
-pub fn find_number_of_possible_passports(records: &Vec<Record>) -> usize {
-    records.iter().filter(|&record| is_passport(record)).count()
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
 }
 
-pub fn find_number_of_valid_passports(records: &Vec<Record>) -> usize {
-    records.iter().filter(|&record| is_passport(record)).filter(|&record| record.fields_are_valid()).count()
+impl From<ReadError> for io::Error {
+    fn from(error: ReadError) -> Self {
+        match error {
+            ReadError::Io(error) => error,
+            ReadError::Parse(error) => io::Error::new(io::ErrorKind::InvalidData, error.to_string()),
+        }
+    }
 }
 
-fn is_byr_valid(value: &str) -> bool {
-    let date: usize = value.parse().unwrap();
-    return date >= 1920 && date <= 2002;
+// Whitespace-separated tokens of a line with their 1-based column.
+fn tokenize(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut symbols = line.char_indices().enumerate().peekable();
+    iter::from_fn(move || {
+        while symbols.next_if(|&(_, (_, symbol))| symbol.is_whitespace()).is_some() {}
+        let (column, (start, _)) = symbols.next()?;
+        while symbols.next_if(|&(_, (_, symbol))| !symbol.is_whitespace()).is_some() {}
+        let end = symbols.peek().map_or(line.len(), |&(_, (offset, _))| offset);
+        Some((column + 1, &line[start..end]))
+    })
 }
 
-fn is_iyr_valid(value: &str) -> bool {
-    let date: usize = value.parse().unwrap();
-    return date >= 2010 && date <= 2020;
+fn parse_line(line: &str, line_number: usize, fields: &mut HashMap<String, String>) -> Result<(), ParseError> {
+    for (column, token) in tokenize(line) {
+        let error = |kind| ParseError { line: line_number, column, kind };
+        let (key, value) = token.split_once(':').ok_or_else(|| error(ParseErrorKind::MissingColon(token.to_string())))?;
+        if key.is_empty() {
+            return Err(error(ParseErrorKind::EmptyKey(token.to_string())));
+        }
+        if fields.insert(key.to_string(), value.to_string()).is_some() {
+            return Err(error(ParseErrorKind::DuplicateKey(key.to_string())));
+        }
+    }
+    Ok(())
 }
 
-fn is_eyr_valid(value: &str) -> bool {
-    let date: usize = value.parse().unwrap();
-    return date <= 2030 && date >= 2020;
+#[cfg(test)]
+pub fn new_record(info: &str) -> Result<Record, ParseError> {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    for (index, line) in info.lines().enumerate() {
+        parse_line(line, index + 1, &mut fields)?;
+    }
+    Ok(Record {fields})
 }
 
-fn is_hgt_valid(value: &str) -> bool {
-    let num = &value[..value.len()-2];
-    if value.contains("cm") {
-        let num: usize = num.parse().unwrap();
-        return num <= 193 && num >= 150;
-    } else if value.contains("in") {
-        let num: usize = num.parse().unwrap();
-        return num <= 76 && num >= 59;
-    } else {
-        false
+// Records separated by blank lines, read one line at a time. A record that fails to
+// parse is reported and skipped, and reading carries on with the next record.
+pub struct Records<R> {
+    lines: io::Lines<R>,
+    line_number: usize,
+    failed: bool,
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = Result<Record, ReadError>;
+
+    // A line that is not valid UTF-8 spoils only its own record, like a parse error, and
+    // the rest of the record is skipped. Any other read error ends the records.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mut fields: HashMap<String, String> = HashMap::new();
+        let mut error = None;
+        let mut has_lines = false;
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(io_error) if io_error.kind() == io::ErrorKind::InvalidData => {
+                    has_lines = true;
+                    error.get_or_insert(ReadError::Io(io_error));
+                    continue;
+                },
+                Err(io_error) => {
+                    self.failed = true;
+                    return Some(Err(ReadError::Io(io_error)));
+                },
+            };
+            if line.trim().is_empty() {
+                if has_lines {
+                    break;
+                }
+                continue;
+            }
+            has_lines = true;
+            if error.is_none() {
+                error = parse_line(&line, self.line_number, &mut fields).err().map(ReadError::Parse);
+            }
+        }
+        match error {
+            Some(error) => Some(Err(error)),
+            None if has_lines => Some(Ok(Record {fields})),
+            None => None,
+        }
     }
 }
 
-fn is_hcl_valid(value: &str) -> bool {
-    let hcl_format = Regex::new(r"^#[a-f0-9]{6}$").unwrap();
-    hcl_format.is_match(value)
+pub fn read_records<R: BufRead>(reader: R) -> Records<R> {
+    Records { lines: reader.lines(), line_number: 0, failed: false }
 }
 
-fn is_ecl_valid(value: &str) -> bool {
-    let colors = ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"];
-    colors.contains(&value)
+pub fn records_from_path(filepath: &str) -> Result<Records<BufReader<File>>, io::Error> {
+    Ok(read_records(BufReader::new(File::open(filepath)?)))
 }
 
-fn is_pid_valid(value: &str) -> bool {
-    let pid_format = Regex::new(r"^[0-9]{9}$").unwrap();
-    pid_format.is_match(value)
+// Every record that parses, along with the errors for the records that were skipped.
+// Only failing to read the file stops it early.
+pub fn create_records_from_path(filepath: &str) -> Result<(Vec<Record>, Vec<ParseError>), io::Error>{
+    let mut records = vec![];
+    let mut skipped = vec![];
+    for record in records_from_path(filepath)? {
+        match record {
+            Ok(record) => records.push(record),
+            Err(ReadError::Parse(error)) => skipped.push(error),
+            Err(ReadError::Io(error)) => return Err(error),
+        }
+    }
+    Ok((records, skipped))
+}
+
+pub fn find_number_of_possible_passports(records: &[Record], schema: &Schema) -> usize {
+    records.iter().filter(|&record| record.has_required_fields(schema)).count()
+}
+
+pub fn find_number_of_valid_passports(records: &[Record], schema: &Schema) -> usize {
+    records.iter().filter(|&record| record.fields_are_valid(schema)).count()
+}
+
+pub fn find_reports_of_passports(records: &[Record], schema: &Schema) -> Vec<Report> {
+    records.iter().map(|record| record.validate(schema)).collect()
 }
 
 #[cfg(test)]
 mod tests {
     
     use super::*;
+    use std::{env, fs};
 
     #[test]
     fn a_record_correctly_instantiated() {
         let info = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\nbyr:1937 iyr:2017 cid:147 hgt:183cm";
-        let record = new_record(info);
+        let record = new_record(info).unwrap();
         assert_eq!(record.fields["ecl"], "gry");
         assert_eq!(record.fields["pid"], "860033327");
         assert_eq!(record.fields["eyr"], "2020");
@@ -139,100 +281,170 @@ mod tests {
     #[test]
     fn full_passport_correctly_detected() {
         let info = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\nbyr:1937 iyr:2017 cid:147 hgt:183cm";
-        let record = new_record(info);
-        assert!(is_passport(&record));
+        let record = new_record(info).unwrap();
+        assert!(record.has_required_fields(&Schema::passport()));
     }
 
     #[test]
     fn invalid_passport_correctly_identified() {
         let info = "iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\nhcl:#cfa07d byr:1929";
-        let record = new_record(info);
-        assert!(!is_passport(&record));
+        let record = new_record(info).unwrap();
+        assert!(!record.has_required_fields(&Schema::passport()));
     }
 
     #[test]
     fn can_create_records_from_path() -> Result<(), io::Error> {
-        let records = create_records_from_path("test_data/test1.txt")?;
+        let (records, _) = create_records_from_path("test_data/test1.txt")?;
         assert_eq!(4, records.len());
         Ok(())
     }
 
     #[test]
     fn number_of_valid_passports_correctly_found_for_part_1() -> Result<(), io::Error> {
-        let records = create_records_from_path("test_data/test1.txt")?;
-        assert_eq!(2, find_number_of_possible_passports(&records));
+        let (records, _) = create_records_from_path("test_data/test1.txt")?;
+        assert_eq!(2, find_number_of_possible_passports(&records, &Schema::passport()));
         Ok(())
     }
 
     #[test]
-    fn check_byr_validity() {
-        let valid_value = "1937";
-        assert!(is_byr_valid(valid_value));
-        let invalid_value = "2003";
-        assert!(!is_byr_valid(invalid_value));
+    fn check_all_mandatory_fields_in_record_valid() {
+        let info = "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
+        let record = new_record(info).unwrap();
+        assert!(record.fields_are_valid(&Schema::passport()));
     }
 
     #[test]
-    fn check_valid_iyr_is_valid() {
-        let valid_value = "2015";
-        assert!(is_iyr_valid(valid_value));
-        let invalid_value = "2021";
-        assert!(!is_iyr_valid(invalid_value));
+    fn record_with_all_necessary_fields_but_failing_values_fail() {
+        let info = "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719blahblah";
+        let record = new_record(info).unwrap();
+        assert!(!record.fields_are_valid(&Schema::passport()));
     }
 
     #[test]
-    fn check_eyr_validity() {
-        let valid_value = "2020";
-        assert!(is_eyr_valid(valid_value));
-        let invalid_value = "2019";
-        assert!(!is_eyr_valid(invalid_value));
+    fn number_of_valid_passports_correctly_found_for_part_2() -> Result<(), io::Error> {
+        let (records, _) = create_records_from_path("test_data/test1.txt")?;
+        assert_eq!(2, find_number_of_valid_passports(&records, &Schema::passport()));
+        Ok(())
     }
 
     #[test]
-    fn check_hgt_validity() {
-        let valid_value_cm = "190cm";
-        assert!(is_hgt_valid(valid_value_cm));
-        let valid_value_in = "60in";
-        assert!(is_hgt_valid(valid_value_in));
-        let invalid_value = "88";
-        assert!(!is_hgt_valid(invalid_value));
+    fn other_document_types_validated_from_their_schema() {
+        let schema = Schema::from_toml("[id]\nrequired = true\npattern = \"^[A-Z]{3}$\"\n\n[age]\nrange = [0, 150]").unwrap();
+        assert!(new_record("id:ABC").unwrap().fields_are_valid(&schema));
+        assert!(new_record("id:ABC age:40").unwrap().fields_are_valid(&schema));
+        assert!(!new_record("id:ABC age:400").unwrap().fields_are_valid(&schema));
+        assert!(!new_record("age:40").unwrap().has_required_fields(&schema));
     }
 
     #[test]
-    fn check_hcl_validity() {
-        let valid_value = "#123abc";
-        assert!(is_hcl_valid(valid_value));
-        let invalid_value = "#123abz";
-        assert!(!is_hcl_valid(invalid_value));
+    fn report_lists_every_problem_with_a_record() {
+        let record = new_record("byr:1900 iyr:abc hgt:5 hcl:#123abc ecl:wat pid:000000001 zzz:1 aaa:2").unwrap();
+        let report = record.validate(&Schema::passport());
+        assert_eq!(report.issues, vec![
+            Issue::OutOfRange { field: "byr".to_string(), value: "1900".to_string(), reason: "1900 is outside 1920-2002".to_string() },
+            Issue::Malformed { field: "iyr".to_string(), value: "abc".to_string(), reason: "expected a whole number".to_string() },
+            Issue::Missing("eyr".to_string()),
+            Issue::Malformed { field: "hgt".to_string(), value: "5".to_string(), reason: "expected a number followed by one of cm, in".to_string() },
+            Issue::OutOfRange { field: "ecl".to_string(), value: "wat".to_string(), reason: "expected one of amb, blu, brn, gry, grn, hzl, oth".to_string() },
+            Issue::Unknown { field: "aaa".to_string(), value: "2".to_string() },
+            Issue::Unknown { field: "zzz".to_string(), value: "1".to_string() },
+        ]);
+        assert!(!record.has_required_fields(&Schema::passport()));
+        assert!(!report.is_valid());
     }
 
     #[test]
-    fn check_ecl_validity() {
-        let valid_value = "brn";
-        assert!(is_ecl_valid(valid_value));
-        let invalid_value = "wat";
-        assert!(!is_ecl_valid(invalid_value));
+    fn unknown_fields_reported_without_failing_the_passport() {
+        let record = new_record("iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719 zzz:1").unwrap();
+        let report = record.validate(&Schema::passport());
+        assert!(report.is_valid());
+        assert!(record.fields_are_valid(&Schema::passport()));
+        assert_eq!(report.to_string(), "valid: zzz:1 is not a known field");
     }
 
     #[test]
-    fn check_pid_validity() {
-        let valid_value = "000000001";
-        assert!(is_pid_valid(valid_value));
-        let invalid_value = "0123456789";
-        assert!(!is_pid_valid(invalid_value));
+    fn per_record_breakdown_found_for_a_batch() -> Result<(), io::Error> {
+        let (records, _) = create_records_from_path("test_data/test1.txt")?;
+        let reports = find_reports_of_passports(&records, &Schema::passport());
+        let summary: Vec<String> = reports.iter().map(|report| report.to_string()).collect();
+        assert_eq!(summary, vec![
+            "valid".to_string(),
+            "invalid: hgt is missing".to_string(),
+            "valid".to_string(),
+            "invalid: byr is missing".to_string(),
+        ]);
+        Ok(())
     }
 
     #[test]
-    fn check_all_mandatory_fields_in_record_valid() {
-        let info = "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
-        let record = new_record(info);
-        assert!(record.fields_are_valid());
+    fn tokens_split_on_any_whitespace() {
+        let record = new_record("  ecl:gry\tpid:860033327   eyr:2020\r\n\nhcl:#fffffd ").unwrap();
+        assert_eq!(record.get("ecl"), Some("gry"));
+        assert_eq!(record.get("pid"), Some("860033327"));
+        assert_eq!(record.get("eyr"), Some("2020"));
+        assert_eq!(record.get("hcl"), Some("#fffffd"));
+        assert_eq!(record.fields.len(), 4);
     }
 
     #[test]
-    fn record_with_all_necessary_fields_but_failing_values_fail() {
-        let info = "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719blahblah";
-        let record = new_record(info);
-        assert!(!record.fields_are_valid());
+    fn values_may_contain_colons() {
+        let record = new_record("url:http://example.com empty:").unwrap();
+        assert_eq!(record.get("url"), Some("http://example.com"));
+        assert_eq!(record.get("empty"), Some(""));
+    }
+
+    #[test]
+    fn malformed_tokens_reported_with_their_position() {
+        assert_eq!(new_record("ecl:gry\n  pid:1 oops").err(), Some(ParseError { line: 2, column: 9, kind: ParseErrorKind::MissingColon("oops".to_string()) }));
+        assert_eq!(new_record("é:1 :2").err(), Some(ParseError { line: 1, column: 5, kind: ParseErrorKind::EmptyKey(":2".to_string()) }));
+        assert_eq!(new_record("ecl:gry\necl:brn").err(), Some(ParseError { line: 2, column: 1, kind: ParseErrorKind::DuplicateKey("ecl".to_string()) }));
+    }
+
+    #[test]
+    fn records_streamed_with_errors_reported_and_skipped() {
+        let data = "\n\necl:gry\nbad\n\n\n\npid:1 cid:2\n\n   \nhgt:1cm\nhgt:2cm\n";
+        let records: Vec<Result<Record, ReadError>> = read_records(data.as_bytes()).collect();
+        assert_eq!(records.len(), 3);
+        match &records[0] {
+            Err(ReadError::Parse(error)) => assert_eq!(error.to_string(), "line 4, column 1: expected key:value, found \"bad\""),
+            _ => panic!("expected a parse error"),
+        }
+        assert_eq!(records[1].as_ref().unwrap().get("cid"), Some("2"));
+        match &records[2] {
+            Err(ReadError::Parse(error)) => assert_eq!(error.to_string(), "line 12, column 1: duplicate key \"hgt\""),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn line_numbers_kept_in_step_after_a_read_error() {
+        let data: &[u8] = b"ecl:gry\n\xff\nbad\n\npid:1\n\nworse\n";
+        let records: Vec<Result<Record, ReadError>> = read_records(data).collect();
+        assert_eq!(records.len(), 3);
+        assert!(matches!(records[0], Err(ReadError::Io(_))));
+        assert_eq!(records[1].as_ref().unwrap().get("pid"), Some("1"));
+        match &records[2] {
+            Err(ReadError::Parse(error)) => assert_eq!(error.line, 7),
+            _ => panic!("expected a parse error"),
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn records_end_after_a_failed_read() -> Result<(), io::Error> {
+        let records: Vec<Result<Record, ReadError>> = records_from_path("test_data")?.collect();
+        assert_eq!(records.len(), 1);
+        assert!(matches!(records[0], Err(ReadError::Io(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn bad_records_skipped_when_reading_a_file() -> Result<(), io::Error> {
+        let filepath = env::temp_dir().join("day_4_bad_records.txt");
+        fs::write(&filepath, "ecl:gry\n\nbad\n\npid:1\n")?;
+        let (records, skipped) = create_records_from_path(filepath.to_str().unwrap())?;
+        fs::remove_file(&filepath)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(skipped, vec![ParseError { line: 3, column: 1, kind: ParseErrorKind::MissingColon("bad".to_string()) }]);
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+use std::fs::read_to_string;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use regex::Regex;
+use toml::{Table, Value};
+
+pub enum Rule {
+    Any,
+    Range(RangeInclusive<u64>),
+    // A number immediately followed by one of the units, each unit with its own range.
+    UnitRange(Vec<(String, RangeInclusive<u64>)>),
+    Pattern(Regex),
+    OneOf(Vec<String>),
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Violation {
+    Malformed(String),
+    OutOfRange(String),
+}
+
+impl Rule {
+    #[cfg(test)]
+    pub fn accepts(&self, value: &str) -> bool {
+        self.check(value).is_ok()
+    }
+
+    pub fn check(&self, value: &str) -> Result<(), Violation> {
+        match self {
+            Rule::Any => Ok(()),
+            Rule::Range(range) => {
+                let num = value.parse().map_err(|_| Violation::Malformed("expected a whole number".to_string()))?;
+                check_range(num, range)
+            },
+            Rule::UnitRange(units) => {
+                let units_list = units.iter().map(|(unit, _)| unit.as_str()).collect::<Vec<_>>().join(", ");
+                let (num, range) = units.iter()
+                    .find_map(|(unit, range)| value.strip_suffix(unit.as_str()).map(|num| (num, range)))
+                    .ok_or(Violation::Malformed(format!("expected a number followed by one of {}", units_list)))?;
+                let num = num.parse().map_err(|_| Violation::Malformed("expected a whole number before the unit".to_string()))?;
+                check_range(num, range)
+            },
+            Rule::Pattern(pattern) if pattern.is_match(value) => Ok(()),
+            Rule::Pattern(pattern) => Err(Violation::Malformed(format!("does not match {}", pattern))),
+            Rule::OneOf(options) if options.iter().any(|option| option == value) => Ok(()),
+            Rule::OneOf(options) => Err(Violation::OutOfRange(format!("expected one of {}", options.join(", ")))),
+        }
+    }
+}
+
+fn check_range(num: u64, range: &RangeInclusive<u64>) -> Result<(), Violation> {
+    if range.contains(&num) {
+        Ok(())
+    } else {
+        Err(Violation::OutOfRange(format!("{} is outside {}-{}", num, range.start(), range.end())))
+    }
+}
+
+// How a value is cleaned up for export; values that cannot be normalised are kept as they are.
+// Centimetres are only converted from the units that the field's `units` rule declares.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Normalise {
+    Keep,
+    Integer,
+    Lowercase,
+    Centimetres,
+}
+
+impl FromStr for Normalise {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "keep" => Ok(Normalise::Keep),
+            "integer" => Ok(Normalise::Integer),
+            "lowercase" => Ok(Normalise::Lowercase),
+            "centimetres" => Ok(Normalise::Centimetres),
+            _ => Err(format!("unknown normalisation \"{}\"", name)),
+        }
+    }
+}
+
+pub struct Field {
+    pub name: String,
+    pub required: bool,
+    pub rule: Rule,
+    pub normalise: Normalise,
+}
+
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Schema {
+    pub fn builder() -> SchemaBuilder {
+        SchemaBuilder { fields: vec![] }
+    }
+
+    pub fn passport() -> Self {
+        Schema::builder()
+            .required("byr", Rule::Range(1920..=2002)).normalised(Normalise::Integer)
+            .required("iyr", Rule::Range(2010..=2020)).normalised(Normalise::Integer)
+            .required("eyr", Rule::Range(2020..=2030)).normalised(Normalise::Integer)
+            .required("hgt", Rule::UnitRange(vec![("cm".to_string(), 150..=193), ("in".to_string(), 59..=76)])).normalised(Normalise::Centimetres)
+            .required("hcl", Rule::Pattern(Regex::new(r"^#[a-f0-9]{6}$").unwrap())).normalised(Normalise::Lowercase)
+            .required("ecl", Rule::OneOf(["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].map(String::from).to_vec())).normalised(Normalise::Lowercase)
+            .required("pid", Rule::Pattern(Regex::new(r"^[0-9]{9}$").unwrap()))
+            .optional("cid", Rule::Any)
+            .build()
+    }
+
+    pub fn from_path(filepath: &str) -> Result<Self, String> {
+        let text = read_to_string(filepath).map_err(|error| format!("{}: {}", filepath, error))?;
+        Schema::from_toml(&text)
+    }
+
+    // One table per field, e.g.
+    //
+    //   [hgt]
+    //   required = true
+    //   units = { cm = [150, 193], in = [59, 76] }
+    //   normalise = "centimetres"
+    //
+    // where the rule is one of `range`, `units`, `pattern` or `one_of`, or absent for any value,
+    // and `normalise` is one of `keep`, `integer`, `lowercase` or `centimetres`.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|error| format!("{}", error))?;
+        let mut builder = Schema::builder();
+        for (name, spec) in &table {
+            let spec = spec.as_table().ok_or(format!("{}: expected a table", name))?;
+            let required = match spec.get("required") {
+                Some(required) => required.as_bool().ok_or(format!("{}: `required` must be a boolean", name))?,
+                None => false,
+            };
+            let normalise = match spec.get("normalise") {
+                Some(normalise) => normalise.as_str()
+                    .ok_or(format!("{}: `normalise` must be a string", name))?
+                    .parse().map_err(|error| format!("{}: {}", name, error))?,
+                None => Normalise::Keep,
+            };
+            builder = builder.field(name, required, rule_from_toml(name, spec)?).normalised(normalise);
+        }
+        Ok(builder.build())
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+pub struct SchemaBuilder {
+    fields: Vec<Field>,
+}
+
+impl SchemaBuilder {
+    pub fn required(self, name: &str, rule: Rule) -> Self {
+        self.field(name, true, rule)
+    }
+
+    pub fn optional(self, name: &str, rule: Rule) -> Self {
+        self.field(name, false, rule)
+    }
+
+    pub fn field(mut self, name: &str, required: bool, rule: Rule) -> Self {
+        self.fields.retain(|field| field.name != name);
+        self.fields.push(Field { name: name.to_string(), required, rule, normalise: Normalise::Keep });
+        self
+    }
+
+    // Sets how the most recently added field is normalised on export.
+    pub fn normalised(mut self, normalise: Normalise) -> Self {
+        if let Some(field) = self.fields.last_mut() {
+            field.normalise = normalise;
+        }
+        self
+    }
+
+    pub fn build(self) -> Schema {
+        Schema { fields: self.fields }
+    }
+}
+
+fn rule_from_toml(name: &str, spec: &Table) -> Result<Rule, String> {
+    let rules: Vec<&str> = ["range", "units", "pattern", "one_of"].into_iter().filter(|key| spec.contains_key(*key)).collect();
+    match rules.as_slice() {
+        [] => Ok(Rule::Any),
+        ["range"] => Ok(Rule::Range(range_from_toml(name, &spec["range"])?)),
+        ["units"] => {
+            let units = spec["units"].as_table().ok_or(format!("{}: `units` must be a table", name))?;
+            let units = units.iter()
+                .map(|(unit, range)| Ok((unit.clone(), range_from_toml(name, range)?)))
+                .collect::<Result<_, String>>()?;
+            Ok(Rule::UnitRange(units))
+        },
+        ["pattern"] => {
+            let pattern = spec["pattern"].as_str().ok_or(format!("{}: `pattern` must be a string", name))?;
+            Regex::new(pattern).map(Rule::Pattern).map_err(|error| format!("{}: {}", name, error))
+        },
+        ["one_of"] => {
+            let options = spec["one_of"].as_array().ok_or(format!("{}: `one_of` must be an array", name))?;
+            let options = options.iter()
+                .map(|option| option.as_str().map(String::from).ok_or(format!("{}: `one_of` must hold strings", name)))
+                .collect::<Result<_, String>>()?;
+            Ok(Rule::OneOf(options))
+        },
+        _ => Err(format!("{}: expected at most one of {}", name, rules.join(", "))),
+    }
+}
+
+fn range_from_toml(name: &str, value: &Value) -> Result<RangeInclusive<u64>, String> {
+    let invalid = || format!("{}: a range must be [low, high] with non-negative integers", name);
+    let bound = |value: &Value| value.as_integer().and_then(|bound| u64::try_from(bound).ok()).ok_or_else(invalid);
+    match value.as_array().map(|bounds| bounds.as_slice()) {
+        Some([low, high]) => Ok(bound(low)?..=bound(high)?),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn check_byr_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("byr").unwrap().rule;
+        assert!(rule.accepts("1937"));
+        assert!(!rule.accepts("2003"));
+    }
+
+    #[test]
+    fn check_iyr_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("iyr").unwrap().rule;
+        assert!(rule.accepts("2015"));
+        assert!(!rule.accepts("2021"));
+    }
+
+    #[test]
+    fn check_eyr_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("eyr").unwrap().rule;
+        assert!(rule.accepts("2020"));
+        assert!(!rule.accepts("2019"));
+    }
+
+    #[test]
+    fn check_hgt_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("hgt").unwrap().rule;
+        assert!(rule.accepts("190cm"));
+        assert!(rule.accepts("60in"));
+        assert!(!rule.accepts("88"));
+        assert!(!rule.accepts("190in"));
+    }
+
+    #[test]
+    fn check_hcl_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("hcl").unwrap().rule;
+        assert!(rule.accepts("#123abc"));
+        assert!(!rule.accepts("#123abz"));
+    }
+
+    #[test]
+    fn check_ecl_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("ecl").unwrap().rule;
+        assert!(rule.accepts("brn"));
+        assert!(!rule.accepts("wat"));
+    }
+
+    #[test]
+    fn check_pid_validity() {
+        let schema = Schema::passport();
+        let rule = &schema.field("pid").unwrap().rule;
+        assert!(rule.accepts("000000001"));
+        assert!(!rule.accepts("0123456789"));
+    }
+
+    #[test]
+    fn builder_keeps_field_order_and_replaces_redeclared_fields() {
+        let schema = Schema::builder()
+            .required("a", Rule::Any)
+            .optional("b", Rule::Any)
+            .optional("a", Rule::Range(1..=2))
+            .build();
+        let names: Vec<&str> = schema.fields().iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a"]);
+        assert!(!schema.field("a").unwrap().required);
+    }
+
+    #[test]
+    fn schema_file_matches_built_in_passport_schema() {
+        let from_file = Schema::from_path("schemas/passport.toml").unwrap();
+        let built_in = Schema::passport();
+        assert_eq!(from_file.fields().len(), built_in.fields().len());
+        let samples = ["1937", "2003", "2015", "2025", "190cm", "60in", "88", "#123abc", "brn", "000000001", ""];
+        for field in built_in.fields() {
+            let loaded = from_file.field(&field.name).unwrap();
+            assert_eq!(loaded.required, field.required);
+            assert_eq!(loaded.normalise, field.normalise);
+            for sample in samples {
+                assert_eq!(loaded.rule.accepts(sample), field.rule.accepts(sample), "{} {}", field.name, sample);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_schemas_rejected() {
+        assert!(Schema::from_toml("byr = 3").is_err());
+        assert!(Schema::from_toml("[byr]\nrange = [1]").is_err());
+        assert!(Schema::from_toml("[byr]\nrange = [-1, 3]").is_err());
+        assert!(Schema::from_toml("[byr]\nrange = [1, 2]\npattern = \"x\"").is_err());
+        assert!(Schema::from_toml("[hcl]\npattern = \"(\"").is_err());
+        assert!(Schema::from_toml("[hcl]\nrequired = \"yes\"").is_err());
+        assert!(Schema::from_toml("[hcl]\nnormalise = \"uppercase\"").is_err());
+    }
+
+    #[test]
+    fn violations_explain_malformed_and_out_of_range_values() {
+        let schema = Schema::passport();
+        let rule = |name: &str| &schema.field(name).unwrap().rule;
+        assert_eq!(rule("byr").check("19x7"), Err(Violation::Malformed("expected a whole number".to_string())));
+        assert_eq!(rule("byr").check("1919"), Err(Violation::OutOfRange("1919 is outside 1920-2002".to_string())));
+        assert_eq!(rule("hgt").check("m"), Err(Violation::Malformed("expected a number followed by one of cm, in".to_string())));
+        assert_eq!(rule("hgt").check("cm"), Err(Violation::Malformed("expected a whole number before the unit".to_string())));
+        assert_eq!(rule("hgt").check("77in"), Err(Violation::OutOfRange("77 is outside 59-76".to_string())));
+        assert_eq!(rule("hcl").check("123abc"), Err(Violation::Malformed("does not match ^#[a-f0-9]{6}$".to_string())));
+        assert_eq!(rule("ecl").check("wat"), Err(Violation::OutOfRange("expected one of amb, blu, brn, gry, grn, hzl, oth".to_string())));
+    }
+
+    #[test]
+    fn rules_never_panic_on_arbitrary_input() {
+        let schema = Schema::passport();
+        let inputs = ["", "c", "in", "99999999999999999999999", "-5", "é", "1é0cm", "#", "🎄cm"];
+        for field in schema.fields() {
+            for input in inputs {
+                assert!(!field.rule.accepts(input) || field.name == "cid");
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
 
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -10,115 +13,367 @@ where P: AsRef<Path>, {
     Ok(io::BufReader::new(file).lines())
 }
 
-fn find_row(partition_code: &str) -> usize {
-    let row_code = &partition_code[0..7];
-    let mut row = 0;
-    let mut change = 128;
-    for letter in row_code.chars() {
-        change /= 2;
-        if let 'B' = letter {
-            row += change;
+#[derive(PartialEq, Eq, Debug)]
+pub enum PassError {
+    WrongLength { pass: String, expected: usize, found: usize },
+    InvalidLetter { pass: String, position: usize, letter: char },
+    RowOutOfRange(usize),
+    ColumnOutOfRange(usize),
+    IdOutOfRange(usize),
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PassError::WrongLength { pass, expected, found } => write!(f, "{}: expected {} letters, found {}", pass, expected, found),
+            PassError::InvalidLetter { pass, position, letter } => write!(f, "{}: unexpected '{}' at position {}", pass, letter, position),
+            PassError::RowOutOfRange(row) => write!(f, "row {} is not on the plane", row),
+            PassError::ColumnOutOfRange(column) => write!(f, "column {} is not on the plane", column),
+            PassError::IdOutOfRange(id) => write!(f, "seat ID {} is not on the plane", id),
         }
     }
-    row
 }
 
-fn find_column(partition_code: &str) -> usize {
-    let column_code = &partition_code[7..];
-    let mut column = 0;
-    let mut change = 8;
-    for letter in column_code.chars() {
-        change /= 2;
-        if let 'R' = letter {
-            column += change;
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Seat {
+    pub row: usize,
+    pub column: usize,
+    pub id: usize,
+}
+
+// A plane with 2^row_bits rows and 2^column_bits columns. Each pair of letters is
+// (lower half, upper half), e.g. ('F', 'B') for rows.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SeatLayout {
+    row_bits: u32,
+    column_bits: u32,
+    row_letters: (char, char),
+    column_letters: (char, char),
+}
+
+impl Default for SeatLayout {
+    fn default() -> Self {
+        SeatLayout { row_bits: 7, column_bits: 3, row_letters: ('F', 'B'), column_letters: ('L', 'R') }
+    }
+}
+
+impl SeatLayout {
+    pub fn new(row_bits: u32, column_bits: u32, row_letters: (char, char), column_letters: (char, char)) -> Option<Self> {
+        if row_bits.checked_add(column_bits).is_none_or(|bits| bits >= usize::BITS) || row_letters.0 == row_letters.1 || column_letters.0 == column_letters.1 {
+            return None;
+        }
+        Some(SeatLayout { row_bits, column_bits, row_letters, column_letters })
+    }
+
+    pub fn rows(&self) -> usize {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> usize {
+        1 << self.column_bits
+    }
+
+    pub fn pass_length(&self) -> usize {
+        (self.row_bits + self.column_bits) as usize
+    }
+
+    pub fn seat(&self, row: usize, column: usize) -> Result<Seat, PassError> {
+        if row >= self.rows() {
+            return Err(PassError::RowOutOfRange(row));
+        }
+        if column >= self.columns() {
+            return Err(PassError::ColumnOutOfRange(column));
+        }
+        Ok(Seat { row, column, id: row * self.columns() + column })
+    }
+
+    pub fn seat_with_id(&self, id: usize) -> Result<Seat, PassError> {
+        if id >= self.rows() * self.columns() {
+            return Err(PassError::IdOutOfRange(id));
+        }
+        self.seat(id / self.columns(), id % self.columns())
+    }
+
+    pub fn decode(&self, pass: &str) -> Result<Seat, PassError> {
+        let letters: Vec<char> = pass.chars().collect();
+        if letters.len() != self.pass_length() {
+            return Err(PassError::WrongLength { pass: pass.to_string(), expected: self.pass_length(), found: letters.len() });
         }
+        let (row_code, column_code) = letters.split_at(self.row_bits as usize);
+        let row = decode_half(row_code, self.row_letters).map_err(|(position, letter)| {
+            PassError::InvalidLetter { pass: pass.to_string(), position, letter }
+        })?;
+        let column = decode_half(column_code, self.column_letters).map_err(|(position, letter)| {
+            PassError::InvalidLetter { pass: pass.to_string(), position: position + row_code.len(), letter }
+        })?;
+        self.seat(row, column)
+    }
+
+    pub fn encode(&self, row: usize, column: usize) -> Result<String, PassError> {
+        let seat = self.seat(row, column)?;
+        Ok(encode_half(seat.row, self.row_bits, self.row_letters) + &encode_half(seat.column, self.column_bits, self.column_letters))
+    }
+
+    pub fn encode_id(&self, id: usize) -> Result<String, PassError> {
+        let seat = self.seat_with_id(id)?;
+        self.encode(seat.row, seat.column)
     }
-    column
 }
 
-fn find_seat_id(partition_code: &str) -> usize {
-    find_row(&partition_code) * 8 + find_column(&partition_code)
+// Reads the letters as binary digits, most significant first. Fails with the
+// position of the first letter that is neither half.
+fn decode_half(code: &[char], (lower, upper): (char, char)) -> Result<usize, (usize, char)> {
+    code.iter().enumerate().try_fold(0, |value, (position, &letter)| match letter {
+        letter if letter == lower => Ok(value * 2),
+        letter if letter == upper => Ok(value * 2 + 1),
+        letter => Err((position, letter)),
+    })
 }
 
-pub fn create_codes_from_path(filepath: &str) -> HashSet<usize>{
-    let mut all_seat_codes = HashSet::<usize>::new();
-    if let Ok(lines) = read_lines(filepath) {
-        for line in lines {
-            if let Ok(code) = line {
-                all_seat_codes.insert(find_seat_id(&code));
-            }
+fn encode_half(value: usize, bits: u32, (lower, upper): (char, char)) -> String {
+    (0..bits).rev().map(|bit| if value >> bit & 1 == 1 { upper } else { lower }).collect()
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum MissingSeatError {
+    NoCandidate,
+    Ambiguous(Vec<usize>),
+}
+
+impl fmt::Display for MissingSeatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MissingSeatError::NoCandidate => write!(f, "no empty seat has occupied seats on both sides"),
+            MissingSeatError::Ambiguous(ids) => write!(f, "several seats could be missing: {:?}", ids),
         }
     }
-    all_seat_codes
 }
 
-pub fn find_highest_id_from(seat_codes: &HashSet<usize>) -> usize {
-    *seat_codes.iter().max().unwrap()
+impl Error for MissingSeatError {}
+
+impl Error for PassError {}
+
+// Which passes claim which seats. Passes are identified by their 1-based line number.
+pub struct Cabin {
+    layout: SeatLayout,
+    rows: RangeInclusive<usize>,
+    claims: BTreeMap<usize, Vec<usize>>,
+    invalid_passes: Vec<(usize, PassError)>,
 }
 
-pub fn find_missing_id_from(seat_codes: &HashSet<usize>) -> Result<usize, bool> {
-    for n in 8..1024 {
-        if !(seat_codes.contains(&n)) && seat_codes.contains(&(n-1)) && seat_codes.contains(&(n+1)) {
-            return Ok(n);
+impl Cabin {
+    pub fn new<S: AsRef<str>>(layout: SeatLayout, passes: &[S]) -> Self {
+        let mut claims: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut invalid_passes = vec![];
+        for (index, pass) in passes.iter().enumerate() {
+            match layout.decode(pass.as_ref()) {
+                Ok(seat) => claims.entry(seat.id).or_default().push(index + 1),
+                Err(error) => invalid_passes.push((index + 1, error)),
+            }
+        }
+        let rows = 0..=layout.rows() - 1;
+        Cabin { layout, rows, claims, invalid_passes }
+    }
+
+    // Limits the cabin to the rows that actually have seats, which must all be on the plane.
+    pub fn with_rows(mut self, rows: RangeInclusive<usize>) -> Result<Self, PassError> {
+        if !rows.is_empty() && *rows.end() >= self.layout.rows() {
+            return Err(PassError::RowOutOfRange(*rows.end()));
+        }
+        self.rows = rows;
+        Ok(self)
+    }
+
+    pub fn is_occupied(&self, id: usize) -> bool {
+        self.claims.contains_key(&id)
+    }
+
+    pub fn occupied_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.claims.keys().copied()
+    }
+
+    pub fn invalid_passes(&self) -> &[(usize, PassError)] {
+        &self.invalid_passes
+    }
+
+    // Empty seats in existing rows whose IDs on either side are both taken.
+    pub fn empty_seats_between_occupied(&self) -> Vec<Seat> {
+        self.occupied_ids()
+            .filter(|&id| !self.is_occupied(id + 1) && self.is_occupied(id + 2))
+            .filter_map(|id| self.layout.seat_with_id(id + 1).ok())
+            .filter(|seat| self.rows.contains(&seat.row))
+            .collect()
+    }
+
+    // Seats claimed by more than one pass, with the lines of those passes.
+    pub fn duplicates(&self) -> Vec<(Seat, Vec<usize>)> {
+        self.claims_where(|_, lines| lines.len() > 1)
+    }
+
+    // Seats claimed in rows that do not exist in this cabin.
+    pub fn out_of_range(&self) -> Vec<(Seat, Vec<usize>)> {
+        self.claims_where(|seat, _| !self.rows.contains(&seat.row))
+    }
+
+    fn claims_where(&self, predicate: impl Fn(&Seat, &[usize]) -> bool) -> Vec<(Seat, Vec<usize>)> {
+        self.claims.iter()
+            .filter_map(|(&id, lines)| self.layout.seat_with_id(id).ok().map(|seat| (seat, lines)))
+            .filter(|(seat, lines)| predicate(seat, lines))
+            .map(|(seat, lines)| (seat, lines.clone()))
+            .collect()
+    }
+
+    // One line per existing row: the row number, then '#' for a taken seat, '!' for a
+    // seat claimed more than once, 'O' for an empty seat between taken ones and '.'
+    // for any other empty seat.
+    pub fn render(&self) -> String {
+        let gaps: HashSet<usize> = self.empty_seats_between_occupied().iter().map(|seat| seat.id).collect();
+        let width = self.rows.end().to_string().len();
+        let mut map = String::new();
+        for row in self.rows.clone() {
+            let seats: String = (0..self.layout.columns()).map(|column| {
+                let id = row * self.layout.columns() + column;
+                match self.claims.get(&id).map(|lines| lines.len()) {
+                    Some(1) => '#',
+                    Some(_) => '!',
+                    None if gaps.contains(&id) => 'O',
+                    None => '.',
+                }
+            }).collect();
+            map.push_str(&format!("{:>width$} {}\n", row, seats, width = width));
         }
+        map
+    }
+}
+
+pub fn read_passes_from_path(filepath: &str) -> Result<Vec<String>, io::Error> {
+    read_lines(filepath)?.collect()
+}
+
+pub fn create_cabin_from_path(filepath: &str, layout: SeatLayout) -> Result<Cabin, io::Error> {
+    Ok(Cabin::new(layout, &read_passes_from_path(filepath)?))
+}
+
+pub fn find_highest_id_from(cabin: &Cabin) -> Option<usize> {
+    cabin.occupied_ids().last()
+}
+
+pub fn find_missing_id_from(cabin: &Cabin) -> Result<usize, MissingSeatError> {
+    match cabin.empty_seats_between_occupied().as_slice() {
+        [] => Err(MissingSeatError::NoCandidate),
+        [seat] => Ok(seat.id),
+        seats => Err(MissingSeatError::Ambiguous(seats.iter().map(|seat| seat.id).collect())),
     }
-    Err(false)
 }
 
 #[cfg(test)]
 mod tests {
-    
+
     use super::*;
 
     #[test]
     fn row_correctly_returned_with_partition_code() {
-        let seat_1 = "FBFBBFFRLR";
-        assert_eq!(44, find_row(&seat_1));
-
-        let seat_2 = "BFFFBBFRRR";
-        assert_eq!(70, find_row(&seat_2));
-
-        let seat_3 = "FFFBBBFRRR";
-        assert_eq!(14, find_row(&seat_3));
-
-        let seat_4 = "BBFFBBFRLL";
-        assert_eq!(102, find_row(&seat_4));
-
+        let layout = SeatLayout::default();
+        assert_eq!(44, layout.decode("FBFBBFFRLR").unwrap().row);
+        assert_eq!(70, layout.decode("BFFFBBFRRR").unwrap().row);
+        assert_eq!(14, layout.decode("FFFBBBFRRR").unwrap().row);
+        assert_eq!(102, layout.decode("BBFFBBFRLL").unwrap().row);
     }
 
     #[test]
     fn column_correctly_returned_with_partition_code() {
-        let seat_1 = "FBFBBFFRLR";
-        assert_eq!(5, find_column(&seat_1));
+        let layout = SeatLayout::default();
+        assert_eq!(5, layout.decode("FBFBBFFRLR").unwrap().column);
+        assert_eq!(7, layout.decode("BFFFBBFRRR").unwrap().column);
+        assert_eq!(7, layout.decode("FFFBBBFRRR").unwrap().column);
+        assert_eq!(4, layout.decode("BBFFBBFRLL").unwrap().column);
+    }
 
-        let seat_2 = "BFFFBBFRRR";
-        assert_eq!(7, find_column(&seat_2));
+    #[test]
+    fn seat_id_correctly_returned_with_partition_code() {
+        let layout = SeatLayout::default();
+        assert_eq!(357, layout.decode("FBFBBFFRLR").unwrap().id);
+        assert_eq!(567, layout.decode("BFFFBBFRRR").unwrap().id);
+        assert_eq!(119, layout.decode("FFFBBBFRRR").unwrap().id);
+        assert_eq!(820, layout.decode("BBFFBBFRLL").unwrap().id);
+    }
 
-        let seat_3 = "FFFBBBFRRR";
-        assert_eq!(7, find_column(&seat_3));
+    #[test]
+    fn highest_id_correctly_found() {
+        let cabin = create_cabin_from_path("test_data/test1.txt", SeatLayout::default()).unwrap();
+        assert_eq!(Some(820), find_highest_id_from(&cabin));
+    }
 
-        let seat_4 = "BBFFBBFRLL";
-        assert_eq!(4, find_column(&seat_4));
+    #[test]
+    fn invalid_passes_rejected() {
+        let layout = SeatLayout::default();
+        assert_eq!(layout.decode("FBFBBFFRL"), Err(PassError::WrongLength { pass: "FBFBBFFRL".to_string(), expected: 10, found: 9 }));
+        assert_eq!(layout.decode("FBFBBFFRLRR"), Err(PassError::WrongLength { pass: "FBFBBFFRLRR".to_string(), expected: 10, found: 11 }));
+        assert_eq!(layout.decode("FBFBBFLRLR"), Err(PassError::InvalidLetter { pass: "FBFBBFLRLR".to_string(), position: 6, letter: 'L' }));
+        assert_eq!(layout.decode("FBFBBFFRBR"), Err(PassError::InvalidLetter { pass: "FBFBBFFRBR".to_string(), position: 8, letter: 'B' }));
+        assert_eq!(layout.decode("FBFBBFFRéR"), Err(PassError::InvalidLetter { pass: "FBFBBFFRéR".to_string(), position: 8, letter: 'é' }));
     }
 
     #[test]
-    fn seat_id_correctly_returned_with_partition_code() {
-        let seat_1 = "FBFBBFFRLR";
-        assert_eq!(357, find_seat_id(&seat_1));
+    fn seats_encoded_back_into_passes() {
+        let layout = SeatLayout::default();
+        assert_eq!(layout.encode(44, 5), Ok("FBFBBFFRLR".to_string()));
+        assert_eq!(layout.encode_id(820), Ok("BBFFBBFRLL".to_string()));
+        assert_eq!(layout.encode(128, 0), Err(PassError::RowOutOfRange(128)));
+        assert_eq!(layout.encode(0, 8), Err(PassError::ColumnOutOfRange(8)));
+        assert_eq!(layout.encode_id(1024), Err(PassError::IdOutOfRange(1024)));
+        for id in 0..1024 {
+            assert_eq!(layout.decode(&layout.encode_id(id).unwrap()).unwrap().id, id);
+        }
+    }
 
-        let seat_2 = "BFFFBBFRRR";
-        assert_eq!(567, find_seat_id(&seat_2));
+    #[test]
+    fn other_plane_geometries_and_alphabets_supported() {
+        let layout = SeatLayout::new(4, 2, ('0', '1'), ('a', 'b')).unwrap();
+        assert_eq!((layout.rows(), layout.columns()), (16, 4));
+        assert_eq!(layout.decode("1010ba"), Ok(Seat { row: 10, column: 2, id: 42 }));
+        assert_eq!(layout.encode_id(42), Ok("1010ba".to_string()));
+        assert_eq!(SeatLayout::new(7, 3, ('F', 'F'), ('L', 'R')), None);
+        assert_eq!(SeatLayout::new(40, 40, ('F', 'B'), ('L', 'R')), None);
+        assert_eq!(SeatLayout::new(u32::MAX, 1, ('F', 'B'), ('L', 'R')), None);
+    }
 
-        let seat_3 = "FFFBBBFRRR";
-        assert_eq!(119, find_seat_id(&seat_3));
+    #[test]
+    fn missing_seat_found_between_occupied_seats() {
+        let layout = SeatLayout::default();
+        let passes: Vec<String> = [40, 41, 43, 44, 45].iter().map(|&id| layout.encode_id(id).unwrap()).collect();
+        let cabin = Cabin::new(layout.clone(), &passes);
+        assert_eq!(find_missing_id_from(&cabin), Ok(42));
+        assert_eq!(find_missing_id_from(&Cabin::new(layout.clone(), &passes[..2])), Err(MissingSeatError::NoCandidate));
+        let passes: Vec<String> = [1, 3, 5].iter().map(|&id| layout.encode_id(id).unwrap()).collect();
+        assert_eq!(find_missing_id_from(&Cabin::new(layout, &passes)), Err(MissingSeatError::Ambiguous(vec![2, 4])));
+    }
 
-        let seat_4 = "BBFFBBFRLL";
-        assert_eq!(820, find_seat_id(&seat_4));
+    #[test]
+    fn duplicate_invalid_and_out_of_range_passes_reported() {
+        let passes = ["FFFFFFFLLL", "FFFFFFBLLR", "BBFFBBFRLL", "FFFFFFFLLL", "FFFFFFFLLX", "BBBBBBBRRR"];
+        let cabin = Cabin::new(SeatLayout::default(), &passes).with_rows(1..=126).unwrap();
+        let first = Seat { row: 0, column: 0, id: 0 };
+        assert_eq!(cabin.duplicates(), vec![(first, vec![1, 4])]);
+        assert_eq!(cabin.out_of_range(), vec![(first, vec![1, 4]), (Seat { row: 127, column: 7, id: 1023 }, vec![6])]);
+        assert_eq!(cabin.invalid_passes(), &[(5, PassError::InvalidLetter { pass: "FFFFFFFLLX".to_string(), position: 9, letter: 'X' })]);
+        assert_eq!(cabin.empty_seats_between_occupied(), vec![]);
     }
 
     #[test]
-    fn highest_id_correctly_found() {
-        let seat_codes = create_codes_from_path("test_data/test1.txt");
-        assert_eq!(820, find_highest_id_from(&seat_codes));
+    fn rows_off_the_plane_rejected() {
+        let cabin = Cabin::new(SeatLayout::default(), &["FFFFFFFLLL"]);
+        assert_eq!(cabin.with_rows(1..=128).err(), Some(PassError::RowOutOfRange(128)));
+        let cabin = Cabin::new(SeatLayout::default(), &["FFFFFFFLLL"]);
+        assert!(cabin.with_rows(0..=127).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cabin_rendered_as_seat_map() {
+        let layout = SeatLayout::new(2, 2, ('F', 'B'), ('L', 'R')).unwrap();
+        let passes = ["FFLL", "FFRL", "FFRR", "FBLL", "FBLL", "BFLR"];
+        let cabin = Cabin::new(layout, &passes);
+        assert_eq!(cabin.render(), "0 #O##\n1 !...\n2 .#..\n3 ....\n");
+        assert_eq!(cabin.with_rows(1..=2).unwrap().render(), "1 !...\n2 .#..\n");
+    }
+}
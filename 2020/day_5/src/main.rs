@@ -1,13 +1,71 @@
 mod binary_boarding;
 use binary_boarding::{
     find_highest_id_from,
-    create_codes_from_path,
+    create_cabin_from_path,
     find_missing_id_from,
+    SeatLayout,
 };
+use std::env;
+use std::error::Error;
 
-fn main() -> Result<(), bool>{
-    let codes = create_codes_from_path("test_data/input.txt");
-    println!("The highet seat ID is {}", find_highest_id_from(&codes));
-    println!("The missing seat ID is {}", find_missing_id_from(&codes)?);
+// Parses "<row bits>,<column bits>,<row letters>,<column letters>", e.g. "7,3,FB,LR".
+fn parse_layout(info: &str) -> Option<SeatLayout> {
+    let letters = |pair: &str| {
+        let mut letters = pair.chars();
+        match (letters.next(), letters.next(), letters.next()) {
+            (Some(lower), Some(upper), None) => Some((lower, upper)),
+            _ => None,
+        }
+    };
+    match info.split(',').collect::<Vec<_>>()[..] {
+        [row_bits, column_bits, row_letters, column_letters] => SeatLayout::new(
+            row_bits.parse().ok()?,
+            column_bits.parse().ok()?,
+            letters(row_letters)?,
+            letters(column_letters)?,
+        ),
+        _ => None,
+    }
+}
+
+// Usage: day_5 [--layout <layout>] [--rows <first>-<last>] [--map | --report | --encode <id>]
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| args.get(index + 1));
+    let layout = match value_of("--layout") {
+        Some(info) => info.and_then(|info| parse_layout(info)).ok_or("expected --layout <row bits>,<column bits>,<row letters>,<column letters>")?,
+        None => SeatLayout::default(),
+    };
+    if let Some(id) = value_of("--encode") {
+        let id = id.and_then(|id| id.parse().ok()).ok_or("expected --encode <seat id>")?;
+        println!("{}", layout.encode_id(id)?);
+        return Ok(());
+    }
+    let mut cabin = create_cabin_from_path("test_data/input.txt", layout)?;
+    if let Some(rows) = value_of("--rows") {
+        let rows = rows.and_then(|rows| rows.split_once('-'))
+            .and_then(|(first, last)| Some(first.parse().ok()?..=last.parse().ok()?))
+            .ok_or("expected --rows <first>-<last>")?;
+        cabin = cabin.with_rows(rows)?;
+    }
+    if let Some((line, error)) = cabin.invalid_passes().first() {
+        return Err(format!("line {}: {}", line, error).into());
+    }
+    if args.iter().any(|arg| arg == "--map") {
+        print!("{}", cabin.render());
+    }
+    if args.iter().any(|arg| arg == "--report") {
+        for (seat, lines) in cabin.duplicates() {
+            println!("seat {} claimed by lines {:?}", seat.id, lines);
+        }
+        for (seat, lines) in cabin.out_of_range() {
+            println!("seat {} in row {} is outside the cabin, claimed by lines {:?}", seat.id, seat.row, lines);
+        }
+    }
+    match find_highest_id_from(&cabin) {
+        Some(id) => println!("The highet seat ID is {}", id),
+        None => println!("There are no boarding passes"),
+    }
+    println!("The missing seat ID is {}", find_missing_id_from(&cabin)?);
     Ok(())
 }
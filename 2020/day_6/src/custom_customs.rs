@@ -1,67 +1,174 @@
 use std::fs::read_to_string;
 use std::io;
-use std::collections::{
-    HashSet,
-    HashMap,
-};
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+pub const QUESTIONS: usize = 26;
 
 pub fn create_data_by_groups(filepath: &str) -> Result<Vec<String>, io::Error> {
     let raw_data = read_to_string(filepath)?;
     Ok(raw_data.split("\n\n").map(|item| item.to_string()).collect())
 }
 
-pub fn find_sum_of_counts(data: &Vec<String>, rule: &dyn Fn(&str) -> usize) -> usize {
-    data.iter().map(|group_data| rule(group_data)).sum()
+pub fn create_groups(data: &[String]) -> Vec<Group> {
+    data.iter().map(|group_data| Group::new(group_data)).collect()
+}
+
+pub fn find_sum_of_counts(groups: &[Group], rule: &dyn Fn(&Group) -> usize) -> usize {
+    groups.iter().map(rule).sum()
+}
+
+pub fn find_pt1_count_from_group(group: &Group) -> usize {
+    group.anyone().count()
 }
 
-pub fn find_pt1_count_from_group(data: &str) -> usize {
-    let mut unique_chars = HashSet::<char>::new();
-    for c in data.chars() {
-        unique_chars.insert(c);
+pub fn find_pt2_count_from_group(group: &Group) -> usize {
+    group.everyone().count()
+}
+
+// The questions a–z answered "yes", one bit per question.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Answers(u32);
+
+impl Answers {
+    pub const ALL: Answers = Answers((1 << QUESTIONS) - 1);
+
+    // Anything other than a–z is ignored.
+    pub fn new(answers: &str) -> Self {
+        Answers(answers.bytes().filter(u8::is_ascii_lowercase).fold(0, |bits, question| bits | 1 << (question - b'a')))
+    }
+
+    pub fn contains(&self, question: char) -> bool {
+        question.is_ascii_lowercase() && self.0 >> (question as u8 - b'a') & 1 == 1
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn questions(&self) -> impl Iterator<Item = char> + '_ {
+        ('a'..='z').filter(|&question| self.contains(question))
     }
-    unique_chars.remove(&'\n');
-    unique_chars.len()
 }
 
-pub fn find_pt2_count_from_group(group_data: &str) -> usize {
-    let participants: Vec<&str> = group_data.split('\n').collect();
-    let mut answer_count = HashMap::<char, usize>::new();
-    for participant in &participants {
-        for c in participant.chars() {
-            if let Some(occurance) = answer_count.get_mut(&c) {
-                *occurance += 1;
-            } else  {
-                answer_count.insert(c, 1);
-            }
-        }
+impl BitOr for Answers {
+    type Output = Answers;
+
+    fn bitor(self, other: Answers) -> Answers {
+        Answers(self.0 | other.0)
+    }
+}
+
+impl BitAnd for Answers {
+    type Output = Answers;
+
+    fn bitand(self, other: Answers) -> Answers {
+        Answers(self.0 & other.0)
+    }
+}
+
+impl BitXor for Answers {
+    type Output = Answers;
+
+    fn bitxor(self, other: Answers) -> Answers {
+        Answers(self.0 ^ other.0)
+    }
+}
+
+impl Not for Answers {
+    type Output = Answers;
+
+    fn not(self) -> Answers {
+        Answers(!self.0) & Answers::ALL
     }
-    let participant_count = participants.len();
-    answer_count.values().filter(|&occurance| occurance == &participant_count).count()
 }
 
+#[derive(PartialEq, Eq, Debug)]
+pub struct Group {
+    people: Vec<Answers>,
+}
 
+impl Group {
+    // One person per non-blank line.
+    pub fn new(group_data: &str) -> Self {
+        let people = group_data.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Answers::new)
+            .collect();
+        Group { people }
+    }
+
+    pub fn size(&self) -> usize {
+        self.people.len()
+    }
+
+    pub fn anyone(&self) -> Answers {
+        self.people.iter().fold(Answers::default(), |union, &answers| union | answers)
+    }
+
+    pub fn everyone(&self) -> Answers {
+        if self.people.is_empty() {
+            return Answers::default();
+        }
+        self.people.iter().fold(Answers::ALL, |intersection, &answers| intersection & answers)
+    }
+
+    pub fn nobody(&self) -> Answers {
+        !self.anyone()
+    }
+
+    // Questions answered by an odd number of people.
+    pub fn symmetric_difference(&self) -> Answers {
+        self.people.iter().fold(Answers::default(), |difference, &answers| difference ^ answers)
+    }
+
+    pub fn by_at_least(&self, people: usize) -> Answers {
+        self.answered_by(|count| count >= people)
+    }
+
+    pub fn by_exactly(&self, people: usize) -> Answers {
+        self.answered_by(|count| count == people)
+    }
+
+    // How many people in the group answered "yes" to each question.
+    pub fn frequencies(&self) -> [usize; QUESTIONS] {
+        let mut frequencies = [0; QUESTIONS];
+        for answers in &self.people {
+            for question in answers.questions() {
+                frequencies[(question as u8 - b'a') as usize] += 1;
+            }
+        }
+        frequencies
+    }
+
+    fn answered_by(&self, predicate: impl Fn(usize) -> bool) -> Answers {
+        let bits = self.frequencies().iter().enumerate()
+            .filter(|&(_, &count)| predicate(count))
+            .fold(0, |bits, (question, _)| bits | 1 << question);
+        Answers(bits)
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    
+
     use super::*;
 
     #[test]
     fn find_pt1_count_from_a_group() {
-        let group1_data = "abcx\nabcy\nabcz";
-        assert_eq!(6, find_pt1_count_from_group(group1_data));
+        let group1_data = Group::new("abcx\nabcy\nabcz");
+        assert_eq!(6, find_pt1_count_from_group(&group1_data));
 
-        let group2_data = "abc";
-        assert_eq!(3, find_pt1_count_from_group(group2_data));
+        let group2_data = Group::new("abc");
+        assert_eq!(3, find_pt1_count_from_group(&group2_data));
 
-        let group2_data = "a\nb\nc";
-        assert_eq!(3, find_pt1_count_from_group(group2_data));
+        let group2_data = Group::new("a\nb\nc");
+        assert_eq!(3, find_pt1_count_from_group(&group2_data));
     }
 
     #[test]
     fn find_pt2_count_from_a_group() {
-        let group1_data = "abcx\nabcy\nabcz";
-        assert_eq!(3, find_pt2_count_from_group(group1_data));
+        let group1_data = Group::new("abcx\nabcy\nabcz");
+        assert_eq!(3, find_pt2_count_from_group(&group1_data));
     }
 
     #[test]
@@ -73,7 +180,37 @@ mod tests {
 
     #[test]
     fn find_sum_of_all_counts_from_plane() {
-        let data = create_data_by_groups("test_data/test1.txt").unwrap();
-        assert_eq!(11, find_sum_of_counts(&data, &find_pt1_count_from_group));
+        let groups = create_groups(&create_data_by_groups("test_data/test1.txt").unwrap());
+        assert_eq!(11, find_sum_of_counts(&groups, &find_pt1_count_from_group));
+        assert_eq!(6, find_sum_of_counts(&groups, &find_pt2_count_from_group));
+    }
+
+    #[test]
+    fn answers_stored_as_bits() {
+        let answers = Answers::new("zab a?\n");
+        assert_eq!(answers.count(), 3);
+        assert!(answers.contains('z') && !answers.contains('c') && !answers.contains('?'));
+        assert_eq!(answers.questions().collect::<String>(), "abz");
+        assert_eq!((!answers).count(), 23);
+    }
+
+    #[test]
+    fn groups_queried_with_set_algebra() {
+        let group = Group::new("abcx\nabcy\nabcz\nax\n");
+        assert_eq!(group.size(), 4);
+        assert_eq!(group.by_at_least(2), Answers::new("abcx"));
+        assert_eq!(group.by_exactly(3), Answers::new("bc"));
+        assert_eq!(group.by_exactly(4), group.everyone());
+        assert_eq!(group.by_at_least(1), group.anyone());
+        assert_eq!(group.nobody().count(), 20);
+        assert_eq!(group.symmetric_difference(), Answers::new("bcyz"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn empty_group_has_no_answers() {
+        let group = Group::new("\n");
+        assert_eq!(group.size(), 0);
+        assert_eq!(group.everyone().count(), 0);
+        assert_eq!(group.nobody(), Answers::ALL);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use crate::custom_customs::Group;
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct QuestionStatistics {
+    pub question: char,
+    pub groups: usize,
+    pub people: usize,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct FlightStatistics {
+    pub groups: usize,
+    pub people: usize,
+    pub questions: Vec<QuestionStatistics>,
+    // Number of groups of each size.
+    pub group_sizes: BTreeMap<usize, usize>,
+    // 1-based numbers of the groups of two or more people who all gave the same answers.
+    pub unanimous_groups: Vec<usize>,
+}
+
+impl FlightStatistics {
+    pub fn new(groups: &[Group]) -> Self {
+        let mut questions: Vec<QuestionStatistics> = ('a'..='z')
+            .map(|question| QuestionStatistics { question, groups: 0, people: 0 })
+            .collect();
+        let mut group_sizes = BTreeMap::new();
+        let mut unanimous_groups = vec![];
+        for (index, group) in groups.iter().enumerate() {
+            for (statistics, count) in questions.iter_mut().zip(group.frequencies()) {
+                statistics.people += count;
+                if count > 0 {
+                    statistics.groups += 1;
+                }
+            }
+            *group_sizes.entry(group.size()).or_insert(0) += 1;
+            if group.size() > 1 && group.anyone() == group.everyone() {
+                unanimous_groups.push(index + 1);
+            }
+        }
+        FlightStatistics {
+            groups: groups.len(),
+            people: groups.iter().map(|group| group.size()).sum(),
+            questions,
+            group_sizes,
+            unanimous_groups,
+        }
+    }
+
+    // Questions answered by the most people, ties included; empty if nobody answered anything.
+    pub fn most_common(&self) -> Vec<char> {
+        match self.questions.iter().map(|statistics| statistics.people).max() {
+            Some(0) | None => vec![],
+            Some(most) => self.questions_answered_by(most),
+        }
+    }
+
+    // Questions answered by the fewest people, ties included.
+    pub fn least_common(&self) -> Vec<char> {
+        match self.questions.iter().map(|statistics| statistics.people).min() {
+            Some(least) => self.questions_answered_by(least),
+            None => vec![],
+        }
+    }
+
+    fn questions_answered_by(&self, people: usize) -> Vec<char> {
+        self.questions.iter()
+            .filter(|statistics| statistics.people == people)
+            .map(|statistics| statistics.question)
+            .collect()
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format!("{} groups, {} people\n\nquestion  groups  people\n", self.groups, self.people);
+        for statistics in &self.questions {
+            table.push_str(&format!("{:<8}  {:>6}  {:>6}\n", statistics.question, statistics.groups, statistics.people));
+        }
+        table.push_str("\ngroup size  groups\n");
+        for (size, count) in &self.group_sizes {
+            table.push_str(&format!("{:>10}  {:>6}\n", size, count));
+        }
+        table.push_str(&format!("\nmost common: {}\n", join(self.most_common().iter())));
+        table.push_str(&format!("least common: {}\n", join(self.least_common().iter())));
+        table.push_str(&format!("unanimous groups: {}\n", join(self.unanimous_groups.iter())));
+        table
+    }
+
+    pub fn to_json(&self) -> String {
+        let questions: Vec<String> = self.questions.iter()
+            .map(|statistics| format!("{{\"question\":\"{}\",\"groups\":{},\"people\":{}}}", statistics.question, statistics.groups, statistics.people))
+            .collect();
+        let group_sizes: Vec<String> = self.group_sizes.iter().map(|(size, count)| format!("\"{}\":{}", size, count)).collect();
+        let quoted = |questions: Vec<char>| questions.iter().map(|question| format!("\"{}\"", question)).collect::<Vec<_>>().join(",");
+        let unanimous: Vec<String> = self.unanimous_groups.iter().map(|group| group.to_string()).collect();
+        format!(
+            "{{\"groups\":{},\"people\":{},\"questions\":[{}],\"group_sizes\":{{{}}},\"most_common\":[{}],\"least_common\":[{}],\"unanimous_groups\":[{}]}}",
+            self.groups, self.people, questions.join(","), group_sizes.join(","),
+            quoted(self.most_common()), quoted(self.least_common()), unanimous.join(","),
+        )
+    }
+}
+
+fn join<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    let items: Vec<String> = items.map(|item| item.to_string()).collect();
+    if items.is_empty() { "none".to_string() } else { items.join(", ") }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::custom_customs::{create_data_by_groups, create_groups};
+
+    fn statistics() -> FlightStatistics {
+        FlightStatistics::new(&create_groups(&create_data_by_groups("test_data/test1.txt").unwrap()))
+    }
+
+    #[test]
+    fn statistics_gathered_per_question() {
+        let statistics = statistics();
+        assert_eq!((statistics.groups, statistics.people), (5, 11));
+        assert_eq!(statistics.questions[0], QuestionStatistics { question: 'a', groups: 4, people: 8 });
+        assert_eq!(statistics.questions[1], QuestionStatistics { question: 'b', groups: 4, people: 4 });
+        assert_eq!(statistics.questions[2], QuestionStatistics { question: 'c', groups: 3, people: 3 });
+        assert_eq!(statistics.questions[25], QuestionStatistics { question: 'z', groups: 0, people: 0 });
+        assert_eq!(statistics.questions.iter().map(|question| question.people).sum::<usize>(), 15);
+    }
+
+    #[test]
+    fn group_sizes_and_unanimous_groups_found() {
+        let statistics = statistics();
+        assert_eq!(statistics.group_sizes, BTreeMap::from([(1, 2), (2, 1), (3, 1), (4, 1)]));
+        assert_eq!(statistics.unanimous_groups, vec![4]);
+    }
+
+    #[test]
+    fn most_and_least_common_questions_include_ties() {
+        let statistics = statistics();
+        assert_eq!(statistics.most_common(), vec!['a']);
+        assert_eq!(statistics.least_common().len(), 23);
+        assert_eq!(FlightStatistics::new(&[]).most_common(), vec![]);
+    }
+
+    #[test]
+    fn statistics_exported_as_table_and_json() {
+        let statistics = statistics();
+        let table = statistics.to_table();
+        assert!(table.starts_with("5 groups, 11 people\n\nquestion  groups  people\na              4       8\n"));
+        assert!(table.ends_with("most common: a\nleast common: d, e, f, g, h, i, j, k, l, m, n, o, p, q, r, s, t, u, v, w, x, y, z\nunanimous groups: 4\n"));
+        let json = statistics.to_json();
+        assert!(json.starts_with("{\"groups\":5,\"people\":11,\"questions\":[{\"question\":\"a\",\"groups\":4,\"people\":8},"));
+        assert!(json.ends_with("\"group_sizes\":{\"1\":2,\"2\":1,\"3\":1,\"4\":1},\"most_common\":[\"a\"],\"least_common\":[\"d\",\"e\",\"f\",\"g\",\"h\",\"i\",\"j\",\"k\",\"l\",\"m\",\"n\",\"o\",\"p\",\"q\",\"r\",\"s\",\"t\",\"u\",\"v\",\"w\",\"x\",\"y\",\"z\"],\"unanimous_groups\":[4]}"));
+    }
+}
@@ -1,15 +1,45 @@
 mod custom_customs;
+mod customs_statistics;
 use custom_customs::{
     create_data_by_groups,
+    create_groups,
     find_sum_of_counts,
     find_pt1_count_from_group,
     find_pt2_count_from_group,
 };
+use customs_statistics::FlightStatistics;
+use std::env;
 use std::io;
 
+// Usage: day_6 [--table | --json | --histogram | --nobody | --odd | --at-least <k> | --exactly <k>]
 fn main() -> Result<(), io::Error>{
     let data = create_data_by_groups("test_data/input.txt")?;
-    println!("The sum of counts from the flight is {}", find_sum_of_counts(&data, &find_pt1_count_from_group));
-    println!("The sum of counts from the flight is {}", find_sum_of_counts(&data, &find_pt2_count_from_group));
+    let groups = create_groups(&data);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let people = || args.get(1).and_then(|people| people.parse::<usize>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} needs a number of people", args[0])));
+    match args.first().map(|arg| arg.as_str()) {
+        Some("--table") => print!("{}", FlightStatistics::new(&groups).to_table()),
+        Some("--json") => println!("{}", FlightStatistics::new(&groups).to_json()),
+        Some("--histogram") => {
+            for statistics in FlightStatistics::new(&groups).questions {
+                println!("{} {}", statistics.question, statistics.people);
+            }
+        },
+        Some("--nobody") => println!("{}", find_sum_of_counts(&groups, &|group| group.nobody().count())),
+        Some("--odd") => println!("{}", find_sum_of_counts(&groups, &|group| group.symmetric_difference().count())),
+        Some("--at-least") => {
+            let people = people()?;
+            println!("{}", find_sum_of_counts(&groups, &|group| group.by_at_least(people).count()));
+        },
+        Some("--exactly") => {
+            let people = people()?;
+            println!("{}", find_sum_of_counts(&groups, &|group| group.by_exactly(people).count()));
+        },
+        _ => {
+            println!("The sum of counts from the flight is {}", find_sum_of_counts(&groups, &find_pt1_count_from_group));
+            println!("The sum of counts from the flight is {}", find_sum_of_counts(&groups, &find_pt2_count_from_group));
+        },
+    }
     Ok(())
 }
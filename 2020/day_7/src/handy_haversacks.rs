@@ -1,87 +1,549 @@
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
-use std::collections::{BTreeMap, HashMap, HashSet};
-use regex::Regex;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Hash, PartialEq, Eq, Debug)]
-pub struct Content {
-    content: BTreeMap<String, u8>,
+#[derive(PartialEq, Eq, Debug)]
+pub struct Rule {
+    pub color: String,
+    pub contents: Vec<(String, u64)>,
 }
 
-impl Content {
-    fn new(tokens: Vec<&str>) -> Self {
-        let mut content = BTreeMap::<String, u8>::new();
-        for token in tokens {
-            if let "no other" | "" = token {
-                break;
-            }
-            content.insert(token[2..].to_string(), token[0..1].parse::<u8>().unwrap());
-        }
-        Self {
-            content,
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ParseErrorKind {
+    ExpectedColor,
+    ExpectedBag,
+    ExpectedWord(&'static str),
+    ExpectedCount,
+    CountTooLarge,
+    UnexpectedText(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::ExpectedColor => write!(f, "expected a colour"),
+            ParseErrorKind::ExpectedBag => write!(f, "expected \"bag\" or \"bags\""),
+            ParseErrorKind::ExpectedWord(word) => write!(f, "expected \"{}\"", word),
+            ParseErrorKind::ExpectedCount => write!(f, "expected a number of bags"),
+            ParseErrorKind::CountTooLarge => write!(f, "number of bags is too large"),
+            ParseErrorKind::UnexpectedText(text) => write!(f, "unexpected \"{}\"", text),
         }
     }
+}
 
-    fn eventually_contains(&self, target_color: &str, rules: &HashMap<String, Content>) -> bool {
-        if self.content.contains_key(target_color) {
-            return true;
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "{}", error),
+            ReadError::Parse(error) => write!(f, "{}", error),
         }
-        if self.content.is_empty() {
-            return false;
+    }
+}
+
+// Words of a rule with their 1-based column; commas and full stops are words of their own.
+fn tokenize(rule: &str) -> Vec<(usize, &str)> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (column, (offset, symbol)) in rule.char_indices().enumerate() {
+        let is_punctuation = symbol == ',' || symbol == '.';
+        if let Some((token_column, token_start)) = start {
+            if symbol.is_whitespace() || is_punctuation {
+                tokens.push((token_column, &rule[token_start..offset]));
+                start = None;
+            }
         }
-        let mut result = false;
-        for color in self.content.keys() {
-            result = result || rules[color].eventually_contains(target_color, rules);
+        if is_punctuation {
+            tokens.push((column + 1, &rule[offset..offset + 1]));
+        } else if !symbol.is_whitespace() && start.is_none() {
+            start = Some((column + 1, offset));
         }
-        result
     }
+    if let Some((token_column, token_start)) = start {
+        tokens.push((token_column, &rule[token_start..]));
+    }
+    tokens
 }
 
-fn tokenize(rule: &str) -> Vec<&str> {
-    let separator = Regex::new(r" bags contain | bag[s., ]*").expect("Invalid regex");
-    return separator.split(rule.trim_end()).collect();
+struct Cursor<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    position: usize,
+    end_column: usize,
 }
 
-pub fn get_rules(filepath: &str) -> HashMap<String, Content> {
-    let mut rules = HashMap::<String, Content>::new();
-    if let Ok(lines) = read_lines(filepath) {
-        for line in lines {
-            if let Ok(rule_info) = line {
-                let tokens = tokenize(&rule_info);
-                rules.insert(tokens[0].to_string(), Content::new(tokens[1..].to_vec()));
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|&(_, token)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.position).map_or(self.end_column, |&(column, _)| column)
+    }
+
+    fn error<T>(&self, kind: ParseErrorKind) -> Result<T, (usize, ParseErrorKind)> {
+        Err((self.column(), kind))
+    }
+
+    fn skip(&mut self, token: &str) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn word(&mut self, word: &'static str) -> Result<(), (usize, ParseErrorKind)> {
+        if self.skip(word) { Ok(()) } else { self.error(ParseErrorKind::ExpectedWord(word)) }
+    }
+
+    // One or more words followed by "bag" or "bags".
+    fn color(&mut self) -> Result<String, (usize, ParseErrorKind)> {
+        let mut words = vec![];
+        loop {
+            match self.peek() {
+                Some("bag" | "bags") if words.is_empty() => return self.error(ParseErrorKind::ExpectedColor),
+                Some("bag" | "bags") => {
+                    self.position += 1;
+                    return Ok(words.join(" "));
+                },
+                Some("," | ".") | None if words.is_empty() => return self.error(ParseErrorKind::ExpectedColor),
+                Some("," | ".") | None => return self.error(ParseErrorKind::ExpectedBag),
+                Some(word) => {
+                    words.push(word);
+                    self.position += 1;
+                },
             }
         }
     }
-    rules
+
+    fn count(&mut self) -> Result<u64, (usize, ParseErrorKind)> {
+        match self.peek() {
+            Some(token) if !token.is_empty() && token.bytes().all(|byte| byte.is_ascii_digit()) => {
+                let count = token.parse().or_else(|_| self.error(ParseErrorKind::CountTooLarge))?;
+                self.position += 1;
+                Ok(count)
+            },
+            _ => self.error(ParseErrorKind::ExpectedCount),
+        }
+    }
 }
 
-pub fn find_num_of_colors_that_could_contain(target_color: &str, rules: &HashMap<String, Content>) -> usize {
-    let mut possible_colors = HashSet::<String>::new();
-    for (color, content) in rules.iter() {
-        for key in content.content.keys() {
-            if possible_colors.contains(key) {
-                possible_colors.insert(color.clone());
+// <colour> bag[s] contain (no other bag[s] | <n> <colour> bag[s][, <n> <colour> bag[s]]...)[.]
+fn parse_rule(rule: &str) -> Result<Rule, (usize, ParseErrorKind)> {
+    let mut cursor = Cursor { tokens: tokenize(rule), position: 0, end_column: rule.chars().count() + 1 };
+    let color = cursor.color()?;
+    cursor.word("contain")?;
+    let mut contents = vec![];
+    if cursor.skip("no") {
+        cursor.word("other")?;
+        if !cursor.skip("bags") && !cursor.skip("bag") {
+            return cursor.error(ParseErrorKind::ExpectedBag);
+        }
+    } else {
+        loop {
+            let count = cursor.count()?;
+            contents.push((cursor.color()?, count));
+            if !cursor.skip(",") {
                 break;
             }
         }
-        if content.eventually_contains(target_color, &rules) {
-            possible_colors.insert(color.clone());
+    }
+    cursor.skip(".");
+    match cursor.peek() {
+        Some(token) => cursor.error(ParseErrorKind::UnexpectedText(token.to_string())),
+        None => Ok(Rule { color, contents }),
+    }
+}
+
+// Bag colours as nodes, with one edge per "n <colour> bags" clause. Colours are numbered
+// in the order they are first seen, whether in a rule or only inside another rule.
+#[derive(Default)]
+pub struct BagGraph {
+    colors: Vec<String>,
+    ids: HashMap<String, usize>,
+    rule_counts: Vec<usize>,
+    contents: Vec<Vec<(usize, u64)>>,
+    containers: Vec<Vec<(usize, u64)>>,
+    totals: OnceCell<Vec<Result<u128, CountError>>>,
+}
+
+impl BagGraph {
+    fn intern(&mut self, color: &str) -> usize {
+        if let Some(&id) = self.ids.get(color) {
+            return id;
+        }
+        let id = self.colors.len();
+        self.colors.push(color.to_string());
+        self.ids.insert(color.to_string(), id);
+        self.rule_counts.push(0);
+        self.contents.push(vec![]);
+        self.containers.push(vec![]);
+        id
+    }
+
+    fn add_rule(&mut self, rule: &Rule) {
+        let id = self.intern(&rule.color);
+        self.rule_counts[id] += 1;
+        for (inner_color, number) in &rule.contents {
+            let number = *number;
+            let inner = self.intern(inner_color);
+            self.contents[id].push((inner, number));
+            self.containers[inner].push((id, number));
+        }
+        self.totals = OnceCell::new();
+    }
+
+    // Colours with a rule of their own; colours only ever seen inside other bags are not counted.
+    pub fn len(&self) -> usize {
+        self.rule_counts.iter().filter(|&&count| count > 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contents(&self, color: &str) -> Vec<(&str, u64)> {
+        self.ids.get(color).map_or(vec![], |&id| {
+            self.contents[id].iter().map(|&(inner, number)| (self.colors[inner].as_str(), number)).collect()
+        })
+    }
+
+    // Every colour that can eventually hold the given colour, not counting the colour itself.
+    pub fn ancestors(&self, color: &str) -> Vec<&str> {
+        self.reachable(color, &self.containers)
+    }
+
+    // Every colour that can eventually end up inside the given colour, not counting the colour itself.
+    pub fn descendants(&self, color: &str) -> Vec<&str> {
+        self.reachable(color, &self.contents)
+    }
+
+    fn reachable(&self, color: &str, edges: &[Vec<(usize, u64)>]) -> Vec<&str> {
+        let Some(&start) = self.ids.get(color) else {
+            return vec![];
+        };
+        let mut seen = vec![false; self.colors.len()];
+        seen[start] = true;
+        let mut queue = VecDeque::from([start]);
+        let mut found = vec![];
+        while let Some(id) = queue.pop_front() {
+            for &(next, _) in &edges[id] {
+                if !seen[next] {
+                    seen[next] = true;
+                    found.push(self.colors[next].as_str());
+                    queue.push_back(next);
+                }
+            }
+        }
+        found
+    }
+
+    // Total bags inside the given colour, worked out for every colour at once on first use.
+    pub fn total_bags_inside(&self, color: &str) -> Result<u128, CountError> {
+        let &id = self.ids.get(color).ok_or_else(|| CountError::UnknownColor(color.to_string()))?;
+        self.totals.get_or_init(|| self.find_totals())[id].clone()
+    }
+
+    // Works up from the bags that hold nothing, finishing each colour once all of its
+    // contents are finished. Colours on or above a cycle are never finished.
+    fn find_totals(&self) -> Vec<Result<u128, CountError>> {
+        let mut totals: Vec<Result<u128, CountError>> = vec![Err(CountError::Cycle); self.colors.len()];
+        let mut unfinished: Vec<usize> = self.contents.iter().map(|contents| contents.len()).collect();
+        let mut ready: Vec<usize> = (0..self.colors.len()).filter(|&id| unfinished[id] == 0).collect();
+        while let Some(id) = ready.pop() {
+            totals[id] = self.contents[id].iter().try_fold(0u128, |total, &(inner, number)| {
+                let inside = with_bags(totals[inner].clone()?, number)?;
+                total.checked_add(inside).ok_or(CountError::Overflow)
+            });
+            for &(outer, _) in &self.containers[id] {
+                unfinished[outer] -= 1;
+                if unfinished[outer] == 0 {
+                    ready.push(outer);
+                }
+            }
+        }
+        totals
+    }
+
+    // Every chain of rules by which the outer colour can end up holding the inner one,
+    // from outer to inner. Colours are not repeated within a path, so each cycle is
+    // followed at most once. Empty if either colour is unknown.
+    pub fn paths(&self, outer_color: &str, inner_color: &str) -> Vec<Vec<&str>> {
+        let (Some(&outer), Some(&inner)) = (self.ids.get(outer_color), self.ids.get(inner_color)) else {
+            return vec![];
+        };
+        let mut leads_to_inner = vec![false; self.colors.len()];
+        for color in self.ancestors(inner_color) {
+            leads_to_inner[self.ids[color]] = true;
+        }
+        let mut paths = vec![];
+        let mut on_path = vec![false; self.colors.len()];
+        let mut path = vec![outer];
+        let mut next_edge = vec![0];
+        on_path[outer] = true;
+        while let (Some(&id), Some(edge)) = (path.last(), next_edge.last_mut()) {
+            match self.contents[id].get(*edge) {
+                Some(&(next, _)) => {
+                    *edge += 1;
+                    if next == inner {
+                        paths.push(path.iter().chain([&inner]).map(|&step| self.colors[step].as_str()).collect());
+                    } else if leads_to_inner[next] && !on_path[next] {
+                        on_path[next] = true;
+                        path.push(next);
+                        next_edge.push(0);
+                    }
+                },
+                None => {
+                    on_path[id] = false;
+                    path.pop();
+                    next_edge.pop();
+                },
+            }
+        }
+        paths
+    }
+
+    // Each rule in the given colour's contents with the bags it accounts for, counting
+    // the bags themselves and everything inside them, so these add up to
+    // `total_bags_inside`. Fails wherever that total does.
+    pub fn breakdown(&self, color: &str) -> Result<Vec<(&str, u64, u128)>, CountError> {
+        self.total_bags_inside(color)?;
+        let id = self.ids[color];
+        self.contents[id].iter()
+            .map(|&(inner, number)| {
+                let inside = self.total_bags_inside(&self.colors[inner])?;
+                Ok((self.colors[inner].as_str(), number, with_bags(inside, number)?))
+            })
+            .collect()
+    }
+
+    // The whole graph in Graphviz DOT, each edge labelled with how many bags it holds.
+    pub fn to_dot(&self) -> String {
+        self.write_dot(0..self.colors.len())
+    }
+
+    // Only the given colour and everything that can end up inside it; None for an unknown colour.
+    pub fn to_dot_from(&self, color: &str) -> Option<String> {
+        let &id = self.ids.get(color)?;
+        let descendants = self.descendants(color).into_iter().map(|color| self.ids[color]);
+        Some(self.write_dot([id].into_iter().chain(descendants)))
+    }
+
+    fn write_dot(&self, ids: impl Iterator<Item = usize>) -> String {
+        let ids: Vec<usize> = ids.collect();
+        let mut dot = String::from("digraph bags {\n");
+        for &id in &ids {
+            dot.push_str(&format!("    {};\n", quote(&self.colors[id])));
+        }
+        for &id in &ids {
+            for &(inner, number) in &self.contents[id] {
+                dot.push_str(&format!("    {} -> {} [label=\"{}\"];\n", quote(&self.colors[id]), quote(&self.colors[inner]), number));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Problems that would make queries on these rules wrong: colours used without a
+    // rule of their own, colours with more than one rule, and cycles. Each cycle is
+    // given as the path around it, starting and ending at the same colour.
+    pub fn validate(&self) -> Result<(), Vec<RuleError>> {
+        let mut errors = vec![];
+        for id in 0..self.colors.len() {
+            match self.rule_counts[id] {
+                0 => errors.push(RuleError::UnknownColor {
+                    color: self.colors[id].clone(),
+                    used_by: self.containers[id].iter().map(|&(outer, _)| self.colors[outer].clone()).collect(),
+                }),
+                1 => (),
+                _ => errors.push(RuleError::DuplicateRule(self.colors[id].clone())),
+            }
+        }
+        errors.extend(self.find_cycles().into_iter().map(RuleError::Cycle));
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // As `validate`, and also reports every rule that can neither hold nor end up
+    // inside the target colour, so plays no part in queries about it.
+    pub fn validate_for(&self, target_color: &str) -> Result<(), Vec<RuleError>> {
+        let mut errors = self.validate().err().unwrap_or_default();
+        match self.ids.get(target_color) {
+            None => errors.push(RuleError::UnknownColor { color: target_color.to_string(), used_by: vec![] }),
+            Some(&target) => {
+                let mut related = vec![false; self.colors.len()];
+                related[target] = true;
+                for color in self.ancestors(target_color).into_iter().chain(self.descendants(target_color)) {
+                    related[self.ids[color]] = true;
+                }
+                errors.extend((0..self.colors.len())
+                    .filter(|&id| !related[id] && self.rule_counts[id] > 0)
+                    .map(|id| RuleError::Unreachable(self.colors[id].clone())));
+            },
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // Depth-first search without recursion, so long chains of rules cannot overflow
+    // the stack. Every edge back onto the current path closes one cycle.
+    fn find_cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles = vec![];
+        let mut seen_back_edges = HashSet::new();
+        let mut on_path = vec![false; self.colors.len()];
+        let mut finished = vec![false; self.colors.len()];
+        for start in 0..self.colors.len() {
+            if finished[start] {
+                continue;
+            }
+            let mut path = vec![start];
+            let mut next_edge = vec![0];
+            on_path[start] = true;
+            while let (Some(&id), Some(edge)) = (path.last(), next_edge.last_mut()) {
+                match self.contents[id].get(*edge) {
+                    Some(&(inner, _)) => {
+                        *edge += 1;
+                        if on_path[inner] {
+                            if seen_back_edges.insert((id, inner)) {
+                                let from = path.iter().position(|&step| step == inner).unwrap();
+                                cycles.push(path[from..].iter().chain([&inner]).map(|&step| self.colors[step].clone()).collect());
+                            }
+                        } else if !finished[inner] {
+                            on_path[inner] = true;
+                            path.push(inner);
+                            next_edge.push(0);
+                        }
+                    },
+                    None => {
+                        on_path[id] = false;
+                        finished[id] = true;
+                        path.pop();
+                        next_edge.pop();
+                    },
+                }
+            }
         }
+        cycles
     }
-    possible_colors.len()
 }
 
-pub fn find_num_of_bags_contained_in(target_color: &str, rules: &HashMap<String, Content>) -> usize {
-    if rules[target_color].content.is_empty() {
-        return 0;
-    } else {
-        let mut total: usize = 0;
-        for (color, number) in &rules[target_color].content {
-            total += *number as usize * find_num_of_bags_contained_in(&color, rules) as usize + *number as usize;
+// `number` bags, each holding `inside` more.
+fn with_bags(inside: u128, number: u64) -> Result<u128, CountError> {
+    inside.checked_add(1).and_then(|bags| bags.checked_mul(number as u128)).ok_or(CountError::Overflow)
+}
+
+// A double-quoted string with backslashes, quotes and control characters escaped, as both
+// DOT and JSON expect.
+pub fn quote(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for symbol in text.chars() {
+        match symbol {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            symbol if symbol.is_control() => escaped.push_str(&format!("\\u{:04x}", symbol as u32)),
+            symbol => escaped.push(symbol),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum RuleError {
+    UnknownColor { color: String, used_by: Vec<String> },
+    DuplicateRule(String),
+    Cycle(Vec<String>),
+    Unreachable(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::UnknownColor { color, used_by } if used_by.is_empty() => write!(f, "{} has no rule", color),
+            RuleError::UnknownColor { color, used_by } => write!(f, "{} has no rule but is held by {}", color, used_by.join(", ")),
+            RuleError::DuplicateRule(color) => write!(f, "{} has more than one rule", color),
+            RuleError::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleError::Unreachable(color) => write!(f, "{} is unrelated to the target colour", color),
+        }
+    }
+}
+
+// Why a colour's bags cannot be counted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CountError {
+    UnknownColor(String),
+    // The colour can end up inside itself, so there is no end to the bags.
+    Cycle,
+    // More bags than fit in a u128.
+    Overflow,
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CountError::UnknownColor(color) => write!(f, "{} has no rule", color),
+            CountError::Cycle => write!(f, "the bags lead round a cycle, so there is no end to them"),
+            CountError::Overflow => write!(f, "there are too many bags to count"),
+        }
+    }
+}
+
+impl FromStr for BagGraph {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut rules = BagGraph::default();
+        for (index, line) in text.lines().enumerate() {
+            rules.add_line(line, index + 1)?;
+        }
+        Ok(rules)
+    }
+}
+
+impl BagGraph {
+    fn add_line(&mut self, line: &str, line_number: usize) -> Result<(), ParseError> {
+        if line.trim().is_empty() {
+            return Ok(());
         }
-        return total;
+        let rule = parse_rule(line).map_err(|(column, kind)| ParseError { line: line_number, column, kind })?;
+        self.add_rule(&rule);
+        Ok(())
+    }
+}
+
+pub fn get_rules(filepath: &str) -> Result<BagGraph, ReadError> {
+    let mut rules = BagGraph::default();
+    for (index, rule_info) in read_lines(filepath).map_err(ReadError::Io)?.enumerate() {
+        let rule_info = rule_info.map_err(ReadError::Io)?;
+        rules.add_line(&rule_info, index + 1).map_err(ReadError::Parse)?;
     }
+    Ok(rules)
+}
+
+pub fn find_num_of_colors_that_could_contain(target_color: &str, rules: &BagGraph) -> usize {
+    rules.ancestors(target_color).len()
+}
+
+pub fn find_num_of_bags_contained_in(target_color: &str, rules: &BagGraph) -> Result<u128, CountError> {
+    rules.total_bags_inside(target_color)
 }
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -95,69 +557,259 @@ mod tests {
     
     use super::*;
 
+    fn rule(color: &str, contents: &[(&str, u64)]) -> Rule {
+        Rule { color: color.to_string(), contents: contents.iter().map(|&(color, count)| (color.to_string(), count)).collect() }
+    }
+
     #[test]
-    fn test_tokenizer_works_on_simple_rules() {
-        let tokens = tokenize("bright white bags contain 1 shiny gold bag.");
-        assert_eq!("bright white", tokens[0]);
-        assert_eq!("1 shiny gold", tokens[1]);
+    fn test_parser_works_on_simple_rules() {
+        assert_eq!(parse_rule("bright white bags contain 1 shiny gold bag."), Ok(rule("bright white", &[("shiny gold", 1)])));
     }
 
     #[test]
-    fn test_tokenizer_works_on_complex_rules() {
-        let tokens = tokenize("dark olive bags contain 3 faded blue bags, 4 dotted black bags.");
-        assert_eq!("dark olive", tokens[0]);
-        assert_eq!("3 faded blue", tokens[1]);
-        assert_eq!("4 dotted black", tokens[2]);
+    fn test_parser_works_on_complex_rules() {
+        assert_eq!(
+            parse_rule("dark olive bags contain 3 faded blue bags, 4 dotted black bags."),
+            Ok(rule("dark olive", &[("faded blue", 3), ("dotted black", 4)])),
+        );
     }
 
     #[test]
-    fn test_tokenizer_works_on_empty_bag_rule() {
-        let tokens = tokenize("faded blue bags contain no other bags.");
-        assert_eq!("faded blue", tokens[0]);
+    fn test_parser_works_on_empty_bag_rule() {
+        assert_eq!(parse_rule("faded blue bags contain no other bags."), Ok(rule("faded blue", &[])));
+    }
+
+    #[test]
+    fn parser_accepts_large_counts_long_colours_and_loose_punctuation() {
+        assert_eq!(
+            parse_rule("  very  pale light red bag contain 12 bright white bags ,18446744073709551615 x bag"),
+            Ok(rule("very pale light red", &[("bright white", 12), ("x", u64::MAX)])),
+        );
+        assert_eq!(parse_rule("a bags contain no other bag"), Ok(rule("a", &[])));
+    }
+
+    #[test]
+    fn parser_reports_error_positions() {
+        assert_eq!(parse_rule("bags contain no other bags."), Err((1, ParseErrorKind::ExpectedColor)));
+        assert_eq!(parse_rule("red bags hold 1 blue bag."), Err((10, ParseErrorKind::ExpectedWord("contain"))));
+        assert_eq!(parse_rule("red bags contain one blue bag."), Err((18, ParseErrorKind::ExpectedCount)));
+        assert_eq!(parse_rule("red bags contain -1 blue bag."), Err((18, ParseErrorKind::ExpectedCount)));
+        assert_eq!(parse_rule("red bags contain 1 blue, 2 green bags."), Err((24, ParseErrorKind::ExpectedBag)));
+        assert_eq!(parse_rule("red bags contain 1 blue"), Err((24, ParseErrorKind::ExpectedBag)));
+        assert_eq!(parse_rule("red bags contain 99999999999999999999 blue bags."), Err((18, ParseErrorKind::CountTooLarge)));
+        assert_eq!(parse_rule("red bags contain no other bags. Really."), Err((33, ParseErrorKind::UnexpectedText("Really".to_string()))));
+        let error = "x bags contain 1 y bag.\nred bags contain 2.".parse::<BagGraph>().err().unwrap();
+        assert_eq!(error.to_string(), "line 2, column 19: expected a colour");
     }
 
     #[test]
     fn can_correctly_create_set_of_all_rules() {
-        let rules = get_rules("test_data/test2_part1.txt");
-        let tokens_1 = tokenize("bright white bags contain 1 shiny gold bag.");
-        let expected_content_1 = Content::new(tokens_1[1..].to_vec());
-        let tokens_2 = tokenize("dark olive bags contain 3 faded blue bags, 4 dotted black bags.");
-        let expected_content_2 = Content::new(tokens_2[1..].to_vec());
-        let tokens_3 = tokenize("faded blue bags contain no other bags.");
-        let expected_content_3 = Content::new(tokens_3[1..].to_vec());
+        let rules = get_rules("test_data/test2_part1.txt").unwrap();
         assert_eq!(3, rules.len());
-        assert_eq!(expected_content_1, rules["bright white"]);
-        assert_eq!(expected_content_2, rules["dark olive"]);
-        assert_eq!(expected_content_3, rules["faded blue"]);
+        assert_eq!(vec![("shiny gold", 1)], rules.contents("bright white"));
+        assert_eq!(vec![("faded blue", 3), ("dotted black", 4)], rules.contents("dark olive"));
+        assert_eq!(Vec::<(&str, u64)>::new(), rules.contents("faded blue"));
+    }
+
+    #[test]
+    fn unreadable_rule_files_reported() {
+        assert!(matches!(get_rules("test_data/missing.txt"), Err(ReadError::Io(_))));
+        assert!(matches!(get_rules("test_data"), Err(ReadError::Io(_))));
+        assert!(get_rules("test_data/input.txt").is_ok());
     }
 
     #[test]
     fn can_obtain_number_of_colors_containing_a_particular_color_for_an_example_with_one_level_of_nexting() {
-        let rules = get_rules("test_data/test1_part1.txt");
+        let rules = get_rules("test_data/test1_part1.txt").unwrap();
         assert_eq!(4, find_num_of_colors_that_could_contain("shiny gold", &rules));
     }
 
     #[test]
     fn can_obtain_number_of_colors_containing_a_particular_color_for_an_example_with_two_levels_of_nexting() {
-        let rules = get_rules("test_data/test3_part1.txt");
+        let rules = get_rules("test_data/test3_part1.txt").unwrap();
         assert_eq!(5, find_num_of_colors_that_could_contain("shiny gold", &rules));
     }
 
     #[test]
     fn can_obtain_the_number_of_bags_an_empty_color_contains() {
-        let rules = get_rules("test_data/test5_part2.txt");
-        assert_eq!(0, find_num_of_bags_contained_in("dotted black", &rules));
+        let rules = get_rules("test_data/test5_part2.txt").unwrap();
+        assert_eq!(Ok(0), find_num_of_bags_contained_in("dotted black", &rules));
     }
 
     #[test]
     fn can_obtain_the_number_of_bags_contained_in_a_simple_color() {
-        let rules = get_rules("test_data/test5_part2.txt");
-        assert_eq!(7, find_num_of_bags_contained_in("dark olive", &rules));
+        let rules = get_rules("test_data/test5_part2.txt").unwrap();
+        assert_eq!(Ok(7), find_num_of_bags_contained_in("dark olive", &rules));
     }
 
     #[test]
     fn can_obtain_the_number_of_bags_a_color_contains() {
-        let rules = get_rules("test_data/test4_part2.txt");
-        assert_eq!(126, find_num_of_bags_contained_in("shiny gold", &rules));
+        let rules = get_rules("test_data/test4_part2.txt").unwrap();
+        assert_eq!(Ok(126), find_num_of_bags_contained_in("shiny gold", &rules));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ancestors_and_descendants_found_through_both_directions() {
+        let rules = get_rules("test_data/test1_part1.txt").unwrap();
+        let mut ancestors = rules.ancestors("shiny gold");
+        ancestors.sort();
+        assert_eq!(ancestors, vec!["bright white", "dark orange", "light red", "muted yellow"]);
+        let mut descendants = rules.descendants("shiny gold");
+        descendants.sort();
+        assert_eq!(descendants, vec!["dark olive", "dotted black", "faded blue", "vibrant plum"]);
+        assert_eq!(rules.ancestors("no such colour"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn unknown_colours_and_cycles_do_not_panic_or_hang() {
+        let mut text = "a bags contain 1 b bag.\nb bags contain 2 a bags.\nc bags contain 3 d bags.\n".to_string();
+        for level in 1..100 {
+            text.push_str(&format!("x{} bags contain 9 x{} bags.\n", level, level - 1));
+        }
+        let rules: BagGraph = text.parse().unwrap();
+        assert_eq!(rules.total_bags_inside("x99"), Err(CountError::Overflow));
+        assert_eq!(rules.total_bags_inside("a"), Err(CountError::Cycle));
+        assert_eq!(rules.total_bags_inside("c"), Ok(3));
+        assert_eq!(rules.total_bags_inside("e"), Err(CountError::UnknownColor("e".to_string())));
+        assert_eq!(find_num_of_colors_that_could_contain("a", &rules), 1);
+        assert_eq!(rules.ancestors("a"), vec!["b"]);
+        assert_eq!(rules.descendants("a"), vec!["b"]);
+    }
+
+    #[test]
+    fn large_generated_rule_sets_stay_fast() {
+        let layers = 2_000;
+        let mut text = String::new();
+        for layer in 0..layers {
+            for color in 0..5 {
+                if layer + 1 == layers {
+                    text.push_str(&format!("c{}x{} bags contain no other bags.\n", layer, color));
+                } else {
+                    text.push_str(&format!("c{}x{} bags contain 1 c{}x0 bag.\n", layer, color, layer + 1));
+                }
+            }
+        }
+        let rules: BagGraph = text.parse().unwrap();
+        assert_eq!(find_num_of_colors_that_could_contain("c1999x0", &rules), 5 * 1_999);
+        assert_eq!(find_num_of_bags_contained_in("c1998x0", &rules), Ok(1));
+        assert_eq!(find_num_of_bags_contained_in("c0x3", &rules), Ok(1_999));
+    }
+
+    #[test]
+    fn valid_rules_pass_validation() {
+        let rules = get_rules("test_data/test1_part1.txt").unwrap();
+        assert_eq!(rules.validate(), Ok(()));
+        assert_eq!(rules.validate_for("shiny gold"), Ok(()));
+    }
+
+    #[test]
+    fn unknown_colours_and_duplicate_rules_reported() {
+        let rules: BagGraph = "a bags contain 1 b bag, 2 c bags.\nc bags contain 1 b bag.\nc bags contain no other bags.".parse().unwrap();
+        assert_eq!(rules.validate(), Err(vec![
+            RuleError::UnknownColor { color: "b".to_string(), used_by: vec!["a".to_string(), "c".to_string()] },
+            RuleError::DuplicateRule("c".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn cycles_reported_with_their_path() {
+        let rules: BagGraph = "a bags contain 1 b bag.\nb bags contain 1 c bag, 1 d bag.\nc bags contain 1 a bag.\nd bags contain 1 d bag.".parse().unwrap();
+        let errors = rules.validate().unwrap_err();
+        assert_eq!(errors, vec![
+            RuleError::Cycle(vec!["a", "b", "c", "a"].into_iter().map(String::from).collect()),
+            RuleError::Cycle(vec!["d", "d"].into_iter().map(String::from).collect()),
+        ]);
+        assert_eq!(errors[0].to_string(), "cycle: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn rules_unrelated_to_the_target_reported() {
+        let rules = get_rules("test_data/test1_part1.txt").unwrap();
+        assert_eq!(rules.validate_for("dark olive"), Err(vec![
+            RuleError::Unreachable("vibrant plum".to_string()),
+        ]));
+        assert_eq!(rules.validate_for("pink"), Err(vec![
+            RuleError::UnknownColor { color: "pink".to_string(), used_by: vec![] },
+        ]));
+    }
+
+    #[test]
+    fn nested_totals_counted_in_wide_integers() {
+        let mut text = String::from("x0 bags contain no other bags.\n");
+        for level in 1..=3 {
+            text.push_str(&format!("x{} bags contain 4000000000 x{} bags.\n", level, level - 1));
+        }
+        let rules: BagGraph = text.parse().unwrap();
+        assert_eq!(find_num_of_bags_contained_in("x3", &rules), Ok(4_000_000_000u128.pow(3) + 4_000_000_000u128.pow(2) + 4_000_000_000));
+    }
+
+    #[test]
+    fn uncountable_bags_reported_by_cause() {
+        let rules: BagGraph = "a bags contain 1 b bag.\nb bags contain 1 a bag.\nc bags contain 2 a bags.".parse().unwrap();
+        assert_eq!(find_num_of_bags_contained_in("a", &rules), Err(CountError::Cycle));
+        assert_eq!(find_num_of_bags_contained_in("c", &rules), Err(CountError::Cycle));
+        assert_eq!(find_num_of_bags_contained_in("d", &rules), Err(CountError::UnknownColor("d".to_string())));
+        let mut text = String::from("x0 bags contain no other bags.\n");
+        for level in 1..=3 {
+            text.push_str(&format!("x{} bags contain {} x{} bags.\n", level, u64::MAX, level - 1));
+        }
+        let rules: BagGraph = text.parse().unwrap();
+        assert_eq!(find_num_of_bags_contained_in("x2", &rules), Ok((u64::MAX as u128 + 1) * u64::MAX as u128));
+        assert_eq!(find_num_of_bags_contained_in("x3", &rules), Err(CountError::Overflow));
+        assert_eq!(CountError::Overflow.to_string(), "there are too many bags to count");
+    }
+
+    #[test]
+    fn every_containment_path_found() {
+        let rules = get_rules("test_data/test1_part1.txt").unwrap();
+        assert_eq!(rules.paths("light red", "shiny gold"), vec![
+            vec!["light red", "bright white", "shiny gold"],
+            vec!["light red", "muted yellow", "shiny gold"],
+        ]);
+        assert_eq!(rules.paths("bright white", "shiny gold"), vec![vec!["bright white", "shiny gold"]]);
+        assert_eq!(rules.paths("shiny gold", "light red"), Vec::<Vec<&str>>::new());
+        assert_eq!(rules.paths("plaid", "shiny gold"), Vec::<Vec<&str>>::new());
+    }
+
+    #[test]
+    fn containment_paths_follow_each_cycle_once() {
+        let rules: BagGraph = "a bags contain 1 b bag.\nb bags contain 1 c bag, 1 a bag.\nc bags contain 1 b bag, 1 d bag.".parse().unwrap();
+        assert_eq!(rules.paths("a", "d"), vec![vec!["a", "b", "c", "d"]]);
+        assert_eq!(rules.paths("a", "a"), vec![vec!["a", "b", "a"]]);
+    }
+
+    #[test]
+    fn totals_broken_down_per_subtree() {
+        let rules = get_rules("test_data/test5_part2.txt").unwrap();
+        assert_eq!(rules.breakdown("shiny gold"), Ok(vec![("dark olive", 1, 8), ("vibrant plum", 2, 24)]));
+        assert_eq!(rules.breakdown("faded blue"), Ok(vec![]));
+        assert_eq!(rules.breakdown("plaid"), Err(CountError::UnknownColor("plaid".to_string())));
+        let rules: BagGraph = "a bags contain 1 b bag, 2 c bags.\nb bags contain 1 a bag.\nc bags contain no other bags.".parse().unwrap();
+        assert_eq!(rules.breakdown("a"), Err(CountError::Cycle));
+    }
+
+    #[test]
+    fn rules_exported_as_dot() {
+        let rules: BagGraph = "light red bags contain 1 bright white bag, 2 muted yellow bags.\nbright white bags contain 1 shiny gold bag.\nshiny gold bags contain no other bags.\nmuted yellow bags contain no other bags.".parse().unwrap();
+        assert_eq!(rules.to_dot(), concat!(
+            "digraph bags {\n",
+            "    \"light red\";\n",
+            "    \"bright white\";\n",
+            "    \"muted yellow\";\n",
+            "    \"shiny gold\";\n",
+            "    \"light red\" -> \"bright white\" [label=\"1\"];\n",
+            "    \"light red\" -> \"muted yellow\" [label=\"2\"];\n",
+            "    \"bright white\" -> \"shiny gold\" [label=\"1\"];\n",
+            "}\n",
+        ));
+        assert_eq!(rules.to_dot_from("bright white"), Some(concat!(
+            "digraph bags {\n",
+            "    \"bright white\";\n",
+            "    \"shiny gold\";\n",
+            "    \"bright white\" -> \"shiny gold\" [label=\"1\"];\n",
+            "}\n",
+        ).to_string()));
+        assert_eq!(rules.to_dot_from("plaid"), None);
+        assert_eq!(quote("a \"b\" \\"), "\"a \\\"b\\\" \\\\\"");
+    }
+}
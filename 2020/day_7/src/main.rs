@@ -1,13 +1,72 @@
 mod handy_haversacks;
+mod packing_plan;
 
 use handy_haversacks::{
     get_rules,
     find_num_of_colors_that_could_contain,
     find_num_of_bags_contained_in,
 };
+use packing_plan::PackingPlan;
+use std::env;
 
+// Usage: day_7 [--paths <colour> | --breakdown <colour> | --check <colour> | --dot [<colour>] | --plan [--json]]
 fn main() {
-    let rules = get_rules("test_data/input.txt");
-    println!("The answer to part 1 is {}", find_num_of_colors_that_could_contain("shiny gold", &rules));
-    println!("The answer to part 2 is {}", find_num_of_bags_contained_in("shiny gold", &rules));
+    let rules = match get_rules("test_data/input.txt") {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+    if rules.is_empty() {
+        eprintln!("test_data/input.txt has no rules");
+        return;
+    }
+    if let Err(errors) = rules.validate() {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        return;
+    }
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>()[..] {
+        ["--paths", color] => {
+            for path in rules.paths(color, "shiny gold") {
+                println!("{}", path.join(" -> "));
+            }
+        },
+        ["--breakdown", color] => match rules.breakdown(color) {
+            Ok(breakdown) => {
+                for (inner, number, bags) in breakdown {
+                    println!("{} {}: {} bags", number, inner, bags);
+                }
+            },
+            Err(error) => eprintln!("Cannot count the bags inside {}: {}", color, error),
+        },
+        ["--check", color] => match rules.validate_for(color) {
+            Ok(()) => println!("every rule bears on {}", color),
+            Err(errors) => {
+                for error in errors {
+                    println!("{}", error);
+                }
+            },
+        },
+        ["--dot"] => print!("{}", rules.to_dot()),
+        ["--dot", color] => match rules.to_dot_from(color) {
+            Some(dot) => print!("{}", dot),
+            None => eprintln!("{} has no rule", color),
+        },
+        ["--plan", ref format @ ..] => match PackingPlan::new("shiny gold", &rules) {
+            Ok(plan) if format == ["--json"] => println!("{}", plan.to_json()),
+            Ok(plan) => print!("{}", plan.to_tree()),
+            Err(error) => eprintln!("shiny gold cannot be packed: {}", error),
+        },
+        _ => {
+            println!("The answer to part 1 is {}", find_num_of_colors_that_could_contain("shiny gold", &rules));
+            match find_num_of_bags_contained_in("shiny gold", &rules) {
+                Ok(bags) => println!("The answer to part 2 is {}", bags),
+                Err(error) => eprintln!("Cannot count the bags inside shiny gold: {}", error),
+            }
+        },
+    }
 }
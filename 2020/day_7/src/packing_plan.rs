@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+use crate::handy_haversacks::{quote, BagGraph, CountError};
+
+// A bag and everything packed inside it. `per_bag` is how many of these one bag a level
+// up holds. Each colour's contents are built once and shared wherever the colour appears.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Packing {
+    pub color: String,
+    pub per_bag: u64,
+    pub contents: Rc<Vec<Packing>>,
+}
+
+// Builds every colour's contents before the colours holding it, without recursion, so
+// shared subtrees are built once and long chains of rules cannot overflow the stack.
+// The rules below the colour must have no cycles.
+fn build_contents(color: &str, rules: &BagGraph) -> Rc<Vec<Packing>> {
+    let mut built: HashMap<&str, Rc<Vec<Packing>>> = HashMap::new();
+    let mut pending = vec![color];
+    while let Some(&current) = pending.last() {
+        if built.contains_key(current) {
+            pending.pop();
+            continue;
+        }
+        let contents = rules.contents(current);
+        let waiting: Vec<&str> = contents.iter()
+            .map(|&(inner, _)| inner)
+            .filter(|inner| !built.contains_key(inner))
+            .collect();
+        if waiting.is_empty() {
+            let packings = contents.iter()
+                .map(|&(inner, per_bag)| Packing { color: inner.to_string(), per_bag, contents: Rc::clone(&built[inner]) })
+                .collect();
+            built.insert(current, Rc::new(packings));
+            pending.pop();
+        } else {
+            pending.extend(waiting);
+        }
+    }
+    built.remove(color).unwrap_or_default()
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct PackingPlan {
+    pub color: String,
+    pub contents: Rc<Vec<Packing>>,
+}
+
+// Dropping the contents one level at a time, since dropping them the usual way recurses
+// once per level and long chains of rules would overflow the stack.
+impl Drop for PackingPlan {
+    fn drop(&mut self) {
+        let mut pending = vec![mem::take(&mut self.contents)];
+        while let Some(contents) = pending.pop() {
+            if let Ok(packings) = Rc::try_unwrap(contents) {
+                pending.extend(packings.into_iter().map(|mut packing| mem::take(&mut packing.contents)));
+            }
+        }
+    }
+}
+
+impl PackingPlan {
+    // Fails wherever `total_bags_inside` does. Since the total fits, so does every number in the plan.
+    pub fn new(color: &str, rules: &BagGraph) -> Result<Self, CountError> {
+        rules.total_bags_inside(color)?;
+        Ok(PackingPlan { color: color.to_string(), contents: build_contents(color, rules) })
+    }
+
+    pub fn total(&self) -> u128 {
+        self.levels().iter().sum()
+    }
+
+    // The bags needed of each colour at each depth, starting with those packed straight
+    // into the outer bag. A colour reached several ways at the same depth is counted once
+    // with the ways added up, so this never walks the whole tree.
+    fn colors_by_level(&self) -> Vec<Vec<(&str, u128)>> {
+        let mut levels = vec![];
+        let mut level: Vec<(&str, &[Packing], u128)> = vec![];
+        let mut parents: Vec<(&[Packing], u128)> = vec![(&self.contents, 1)];
+        while !parents.is_empty() {
+            let mut index: HashMap<&str, usize> = HashMap::new();
+            for (contents, number) in parents {
+                for packing in contents {
+                    let number = number * packing.per_bag as u128;
+                    match index.get(packing.color.as_str()) {
+                        Some(&position) => level[position].2 += number,
+                        None => {
+                            index.insert(&packing.color, level.len());
+                            level.push((&packing.color, &packing.contents, number));
+                        },
+                    }
+                }
+            }
+            if level.is_empty() {
+                break;
+            }
+            levels.push(level.iter().map(|&(color, _, number)| (color, number)).collect());
+            parents = level.drain(..).map(|(_, contents, number)| (contents, number)).collect();
+        }
+        levels
+    }
+
+    // Bags needed at each depth, starting with those packed straight into the outer bag.
+    pub fn levels(&self) -> Vec<u128> {
+        self.colors_by_level().iter().map(|level| level.iter().map(|&(_, number)| number).sum()).collect()
+    }
+
+    // Bags needed of each colour, in the order each colour first appears going down the levels.
+    pub fn bill_of_materials(&self) -> Vec<(&str, u128)> {
+        let mut bill: Vec<(&str, u128)> = vec![];
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        for (color, number) in self.colors_by_level().into_iter().flatten() {
+            match index.get(color) {
+                Some(&position) => bill[position].1 += number,
+                None => {
+                    index.insert(color, bill.len());
+                    bill.push((color, number));
+                },
+            }
+        }
+        bill
+    }
+
+    pub fn summary(&self) -> String {
+        let bill: Vec<String> = self.bill_of_materials().iter().map(|(color, number)| format!("{} {}", number, color)).collect();
+        if bill.is_empty() {
+            format!("{} holds no other bags", self.color)
+        } else {
+            format!("you need {} bags: {}", self.total(), bill.join(", "))
+        }
+    }
+
+    // Every bag in the plan on its own line, indented by depth, with how many of it the
+    // whole plan needs at that point in the tree.
+    pub fn to_tree(&self) -> String {
+        let mut tree = format!("{}\n", self.color);
+        let mut stack = vec![(self.contents.iter(), 1u128)];
+        while let Some((contents, number)) = stack.last_mut() {
+            let number = *number;
+            match contents.next() {
+                Some(packing) => {
+                    let number = number * packing.per_bag as u128;
+                    tree.push_str(&format!("{}{} {}\n", "  ".repeat(stack.len()), number, packing.color));
+                    stack.push((packing.contents.iter(), number));
+                },
+                None => {
+                    stack.pop();
+                },
+            }
+        }
+        let levels: Vec<String> = self.levels().iter().map(|number| number.to_string()).collect();
+        tree.push_str(&format!("\nper level: {}\n{}\n", levels.join(", "), self.summary()));
+        tree
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut contents = String::from("[");
+        let mut stack = vec![(self.contents.iter(), 1u128, true)];
+        while let Some((packings, number, first)) = stack.last_mut() {
+            let number = *number;
+            match packings.next() {
+                Some(packing) => {
+                    if !mem::replace(first, false) {
+                        contents.push(',');
+                    }
+                    let number = number * packing.per_bag as u128;
+                    contents.push_str(&format!("{{\"color\":{},\"number\":{},\"contents\":[", quote(&packing.color), number));
+                    stack.push((packing.contents.iter(), number, true));
+                },
+                None => {
+                    stack.pop();
+                    contents.push_str(if stack.is_empty() { "]" } else { "]}" });
+                },
+            }
+        }
+        let levels: Vec<String> = self.levels().iter().map(|number| number.to_string()).collect();
+        let bill: Vec<String> = self.bill_of_materials().iter()
+            .map(|(color, number)| format!("{{\"color\":{},\"number\":{}}}", quote(color), number))
+            .collect();
+        format!(
+            "{{\"color\":{},\"total\":{},\"contents\":{},\"levels\":[{}],\"bill_of_materials\":[{}]}}",
+            quote(&self.color), self.total(), contents, levels.join(","), bill.join(","),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::handy_haversacks::get_rules;
+
+    #[test]
+    fn plan_expanded_with_levels_and_bill_of_materials() {
+        let rules = get_rules("test_data/test4_part2.txt").unwrap();
+        let plan = PackingPlan::new("shiny gold", &rules).unwrap();
+        assert_eq!(plan.levels(), vec![2, 4, 8, 16, 32, 64]);
+        assert_eq!(plan.total(), 126);
+        assert_eq!(plan.bill_of_materials()[..2], [("dark red", 2), ("dark orange", 4)]);
+        assert_eq!(plan.summary(), "you need 126 bags: 2 dark red, 4 dark orange, 8 dark yellow, 16 dark green, 32 dark blue, 64 dark violet");
+    }
+
+    #[test]
+    fn colours_reached_more_than_one_way_merged_in_bill() {
+        let rules = get_rules("test_data/test5_part2.txt").unwrap();
+        let plan = PackingPlan::new("shiny gold", &rules).unwrap();
+        assert_eq!(plan.contents[1], Packing {
+            color: "vibrant plum".to_string(),
+            per_bag: 2,
+            contents: Rc::new(vec![
+                Packing { color: "faded blue".to_string(), per_bag: 5, contents: Rc::default() },
+                Packing { color: "dotted black".to_string(), per_bag: 6, contents: Rc::default() },
+            ]),
+        });
+        assert!(Rc::ptr_eq(&plan.contents[0].contents[0].contents, &plan.contents[1].contents[0].contents));
+        assert_eq!(plan.levels(), vec![3, 29]);
+        assert_eq!(plan.bill_of_materials(), vec![("dark olive", 1), ("vibrant plum", 2), ("faded blue", 13), ("dotted black", 16)]);
+        assert_eq!(Ok(plan.total()), rules.total_bags_inside("shiny gold"));
+    }
+
+    #[test]
+    fn no_plan_for_unknown_colours_or_cycles() {
+        let rules: BagGraph = "a bags contain 1 b bag.\nb bags contain 1 a bag.\nc bags contain no other bags.".parse().unwrap();
+        assert_eq!(PackingPlan::new("a", &rules), Err(CountError::Cycle));
+        assert_eq!(PackingPlan::new("plaid", &rules), Err(CountError::UnknownColor("plaid".to_string())));
+        let plan = PackingPlan::new("c", &rules).unwrap();
+        assert_eq!((plan.total(), plan.levels()), (0, vec![]));
+        assert_eq!(plan.summary(), "c holds no other bags");
+    }
+
+    #[test]
+    fn plan_printed_as_tree_and_json() {
+        let rules: BagGraph = "a bags contain 2 b bags, 1 c bag.\nb bags contain 3 c bags.\nc bags contain no other bags.".parse().unwrap();
+        let plan = PackingPlan::new("a", &rules).unwrap();
+        assert_eq!(plan.to_tree(), "a\n  2 b\n    6 c\n  1 c\n\nper level: 3, 6\nyou need 9 bags: 2 b, 7 c\n");
+        assert_eq!(plan.to_json(), concat!(
+            "{\"color\":\"a\",\"total\":9,\"contents\":[",
+            "{\"color\":\"b\",\"number\":2,\"contents\":[{\"color\":\"c\",\"number\":6,\"contents\":[]}]},",
+            "{\"color\":\"c\",\"number\":1,\"contents\":[]}],",
+            "\"levels\":[3,6],\"bill_of_materials\":[{\"color\":\"b\",\"number\":2},{\"color\":\"c\",\"number\":7}]}",
+        ));
+    }
+
+    #[test]
+    fn shared_subtrees_and_long_chains_expanded_cheaply() {
+        let mut text = String::new();
+        for level in 0..200 {
+            text.push_str(&format!("x{} bags contain 1 y{} bag, 1 z{} bag.\n", level, level, level));
+            text.push_str(&format!("y{} bags contain 1 x{} bag.\nz{} bags contain 1 x{} bag.\n", level, level + 1, level, level + 1));
+        }
+        text.push_str("x200 bags contain no other bags.\n");
+        let rules: BagGraph = text.parse().unwrap();
+        let plan = PackingPlan::new("x100", &rules).unwrap();
+        assert_eq!(plan.levels()[..4], [2, 2, 4, 4]);
+        assert_eq!(plan.bill_of_materials()[..3], [("y100", 1), ("z100", 1), ("x101", 2)]);
+        let mut text = String::new();
+        for level in 0..20_000 {
+            text.push_str(&format!("c{} bags contain 1 c{} bag.\n", level, level + 1));
+        }
+        let rules: BagGraph = text.parse().unwrap();
+        let plan = PackingPlan::new("c0", &rules).unwrap();
+        assert_eq!(plan.total(), 20_000);
+        let json = plan.to_json();
+        assert!(json.contains("{\"color\":\"c20000\",\"number\":1,\"contents\":[]}]}"));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+    }
+
+    #[test]
+    fn control_characters_escaped_in_json() {
+        assert_eq!(quote("a\nb\tc\u{1}\"\\"), "\"a\\nb\\tc\\u0001\\\"\\\\\"");
+    }
+}
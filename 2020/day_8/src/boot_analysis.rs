@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+use crate::handheld_halting::{GameConsole, Instruction};
+
+// Where control can go after an instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    Instruction(usize),
+    End,
+    OutOfBounds(isize),
+    // A registered instruction, whose handler could send control anywhere.
+    Unknown,
+}
+
+// A run of instructions only ever entered at the first and left after the last.
+// `exits` holds block indices in place of instruction indices.
+#[derive(PartialEq, Eq, Debug)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub exits: Vec<Target>,
+}
+
+pub struct ControlFlowGraph {
+    instructions: Vec<Instruction>,
+    targets: Vec<Vec<Target>>,
+    blocks: Vec<BasicBlock>,
+    reachable: Vec<bool>,
+    loops: Vec<Vec<usize>>,
+}
+
+// A position too far off to fit in an `isize` is reported as the nearest one that does.
+fn offset_target(instructions: &[Instruction], index: usize, offset: isize) -> Target {
+    match index.checked_add_signed(offset) {
+        Some(next) if next < instructions.len() => Target::Instruction(next),
+        Some(next) if next == instructions.len() => Target::End,
+        _ => Target::OutOfBounds((index as isize).saturating_add(offset)),
+    }
+}
+
+fn find_targets(instructions: &[Instruction], index: usize) -> Vec<Target> {
+    match instructions[index] {
+        Instruction::Acc(_) | Instruction::Nop(_) => vec![offset_target(instructions, index, 1)],
+        Instruction::Jmp(offset) => vec![offset_target(instructions, index, offset)],
+        Instruction::Custom(..) => vec![Target::Unknown],
+    }
+}
+
+impl ControlFlowGraph {
+    // Analyses the console's program as it stands, patches included.
+    pub fn new(console: &GameConsole) -> Self {
+        let instructions = console.instructions();
+        let targets: Vec<Vec<Target>> = (0..instructions.len())
+            .map(|index| find_targets(&instructions, index))
+            .collect();
+        let mut leaders = vec![false; instructions.len()];
+        if let Some(first) = leaders.first_mut() {
+            *first = true;
+        }
+        for (index, targets) in targets.iter().enumerate() {
+            if targets[..] != [offset_target(&instructions, index, 1)] {
+                for target in targets.iter().chain(&[offset_target(&instructions, index, 1)]) {
+                    if let &Target::Instruction(next) = target {
+                        leaders[next] = true;
+                    }
+                }
+            }
+        }
+        let starts: Vec<usize> = (0..instructions.len()).filter(|&index| leaders[index]).collect();
+        let block_of = |index: usize| starts.partition_point(|&start| start <= index) - 1;
+        let blocks: Vec<BasicBlock> = starts.iter().enumerate()
+            .map(|(block, &start)| {
+                let end = starts.get(block + 1).copied().unwrap_or(instructions.len());
+                let exits = targets[end - 1].iter()
+                    .map(|&target| match target {
+                        Target::Instruction(next) => Target::Instruction(block_of(next)),
+                        target => target,
+                    })
+                    .collect();
+                BasicBlock { start, end, exits }
+            })
+            .collect();
+        let mut cfg = ControlFlowGraph { instructions, targets, blocks, reachable: vec![], loops: vec![] };
+        cfg.reachable = cfg.find_reachable();
+        cfg.loops = cfg.find_loops();
+        cfg
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    pub fn block_of(&self, index: usize) -> Option<usize> {
+        (index < self.instructions.len()).then(|| self.blocks.partition_point(|block| block.start <= index) - 1)
+    }
+
+    fn successors(&self, block: usize) -> impl Iterator<Item = usize> + '_ {
+        self.blocks[block].exits.iter().filter_map(|&exit| match exit {
+            Target::Instruction(next) => Some(next),
+            _ => None,
+        })
+    }
+
+    // Once a reachable block leaves by an unknown target, any block might be next.
+    fn find_reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut queue = VecDeque::new();
+        if !self.blocks.is_empty() {
+            reachable[0] = true;
+            queue.push_back(0);
+        }
+        while let Some(block) = queue.pop_front() {
+            if self.blocks[block].exits.contains(&Target::Unknown) {
+                return vec![true; self.blocks.len()];
+            }
+            for next in self.successors(block) {
+                if !reachable[next] {
+                    reachable[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reachable
+    }
+
+    #[cfg(test)]
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.reachable[block]
+    }
+
+    // Instructions no run from the start can ever get to.
+    pub fn unreachable_instructions(&self) -> Vec<usize> {
+        self.blocks.iter().enumerate()
+            .filter(|&(block, _)| !self.reachable[block])
+            .flat_map(|(_, block)| block.start..block.end)
+            .collect()
+    }
+
+    // Each loop as the blocks in it, in order: the strongly connected components with more
+    // than one block, or with a block that jumps back to itself.
+    pub fn loops(&self) -> &[Vec<usize>] {
+        &self.loops
+    }
+
+    // Kosaraju's algorithm: blocks ordered by when a depth-first search finishes with them,
+    // then searched again in reverse order over the reversed edges.
+    fn find_loops(&self) -> Vec<Vec<usize>> {
+        let mut finished = vec![];
+        let mut seen = vec![false; self.blocks.len()];
+        for start in 0..self.blocks.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut stack = vec![(start, self.successors(start).collect::<Vec<_>>())];
+            while let Some((block, pending)) = stack.last_mut() {
+                match pending.pop() {
+                    Some(next) if !seen[next] => {
+                        seen[next] = true;
+                        stack.push((next, self.successors(next).collect()));
+                    },
+                    Some(_) => (),
+                    None => {
+                        finished.push(*block);
+                        stack.pop();
+                    },
+                }
+            }
+        }
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for block in 0..self.blocks.len() {
+            for next in self.successors(block) {
+                predecessors[next].push(block);
+            }
+        }
+        let mut assigned = vec![false; self.blocks.len()];
+        let mut loops = vec![];
+        for &start in finished.iter().rev() {
+            if assigned[start] {
+                continue;
+            }
+            assigned[start] = true;
+            let mut members = vec![start];
+            let mut stack = vec![start];
+            while let Some(block) = stack.pop() {
+                for &previous in &predecessors[block] {
+                    if !assigned[previous] {
+                        assigned[previous] = true;
+                        members.push(previous);
+                        stack.push(previous);
+                    }
+                }
+            }
+            if members.len() > 1 || self.successors(start).any(|next| next == start) {
+                members.sort_unstable();
+                loops.push(members);
+            }
+        }
+        loops.sort();
+        loops
+    }
+
+    fn label(&self, target: Target) -> String {
+        match target {
+            Target::Instruction(block) => format!("L{}", block),
+            Target::End => "end".to_string(),
+            Target::OutOfBounds(position) => format!("out of bounds ({})", position),
+            Target::Unknown => "unknown".to_string(),
+        }
+    }
+
+    // One line per instruction under a label for each block. Anything but a plain step to
+    // the next instruction is followed by the blocks it can go to.
+    pub fn disassemble(&self) -> String {
+        let mut listing = String::new();
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            let mut notes = vec![];
+            if !self.reachable[block] {
+                notes.push("unreachable");
+            }
+            if self.loops.iter().any(|members| members.contains(&block)) {
+                notes.push("loop");
+            }
+            if notes.is_empty() {
+                listing.push_str(&format!("L{}:\n", block));
+            } else {
+                listing.push_str(&format!("L{}:  ; {}\n", block, notes.join(", ")));
+            }
+            for index in basic_block.start..basic_block.end {
+                let instruction = self.instructions[index].to_string();
+                if self.targets[index][..] == [offset_target(&self.instructions, index, 1)] {
+                    listing.push_str(&format!("{:>4}  {}\n", index, instruction));
+                } else {
+                    let targets: Vec<String> = self.targets[index].iter()
+                        .map(|&target| match target {
+                            Target::Instruction(next) => self.label(Target::Instruction(self.block_of(next).unwrap())),
+                            target => self.label(target),
+                        })
+                        .collect();
+                    listing.push_str(&format!("{:>4}  {:<10}  ; -> {}\n", index, instruction, targets.join(", ")));
+                }
+            }
+        }
+        listing
+    }
+
+    // Blocks as boxes listing their instructions, with unreachable blocks dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            let lines: String = (basic_block.start..basic_block.end)
+                .map(|index| format!("{}  {}\\l", index, self.instructions[index]))
+                .collect();
+            let style = if self.reachable[block] { "" } else { ", style=dashed" };
+            dot.push_str(&format!("    L{} [label=\"L{}:\\l{}\"{}];\n", block, block, lines, style));
+        }
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            for &exit in &basic_block.exits {
+                dot.push_str(&format!("    L{} -> \"{}\";\n", block, self.label(exit)));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::instruction_set::InstructionSet;
+
+    fn example() -> ControlFlowGraph {
+        ControlFlowGraph::new(&GameConsole::new("test_data/test1.txt").unwrap())
+    }
+
+    #[test]
+    fn program_split_into_basic_blocks() {
+        let cfg = example();
+        let starts: Vec<usize> = cfg.blocks().iter().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0, 1, 3, 5, 6, 8]);
+        assert_eq!(cfg.blocks()[1], BasicBlock { start: 1, end: 3, exits: vec![Target::Instruction(4)] });
+        assert_eq!(cfg.blocks()[5], BasicBlock { start: 8, end: 9, exits: vec![Target::End] });
+        assert_eq!(cfg.block_of(7), Some(4));
+        assert_eq!(cfg.block_of(9), None);
+    }
+
+    #[test]
+    fn loops_and_unreachable_instructions_found() {
+        let cfg = example();
+        assert_eq!(cfg.loops(), &[vec![1, 2, 4]]);
+        assert_eq!(cfg.unreachable_instructions(), vec![5, 8]);
+        let cfg = ControlFlowGraph::new(&"jmp +0\njmp -3".parse().unwrap());
+        assert_eq!(cfg.loops(), &[vec![0]]);
+        assert_eq!(cfg.blocks()[1].exits, vec![Target::OutOfBounds(-2)]);
+        assert!(!cfg.is_reachable(1));
+        let cfg = ControlFlowGraph::new(&"nop +0\njmp +9223372036854775807".parse().unwrap());
+        assert_eq!(cfg.blocks()[0].exits, vec![Target::OutOfBounds(isize::MAX)]);
+    }
+
+    #[test]
+    fn registered_instructions_lead_anywhere() {
+        let console = GameConsole::parse("acc +2\njnz +2\njmp +2\nout +0\nmul +0", InstructionSet::extended()).unwrap();
+        let cfg = ControlFlowGraph::new(&console);
+        assert_eq!(cfg.blocks().len(), 4);
+        assert_eq!(cfg.blocks()[0].exits, vec![Target::Unknown]);
+        assert!((0..4).all(|block| cfg.is_reachable(block)));
+        assert_eq!(cfg.loops(), &[] as &[Vec<usize>]);
+        assert_eq!(cfg.disassemble(), concat!(
+            "L0:\n",
+            "   0  acc +2\n",
+            "   1  jnz +2      ; -> unknown\n",
+            "L1:\n",
+            "   2  jmp +2      ; -> L3\n",
+            "L2:\n",
+            "   3  out +0      ; -> unknown\n",
+            "L3:\n",
+            "   4  mul +0      ; -> unknown\n",
+        ));
+        assert!(cfg.to_dot().contains("    L0 -> \"unknown\";\n"));
+    }
+
+    #[test]
+    fn program_disassembled_with_labels() {
+        assert_eq!(example().disassemble(), concat!(
+            "L0:\n",
+            "   0  nop +0\n",
+            "L1:  ; loop\n",
+            "   1  acc +1\n",
+            "   2  jmp +4      ; -> L4\n",
+            "L2:  ; loop\n",
+            "   3  acc +3\n",
+            "   4  jmp -3      ; -> L1\n",
+            "L3:  ; unreachable\n",
+            "   5  acc -99\n",
+            "L4:  ; loop\n",
+            "   6  acc +1\n",
+            "   7  jmp -4      ; -> L2\n",
+            "L5:  ; unreachable\n",
+            "   8  acc +6\n",
+        ));
+    }
+
+    #[test]
+    fn graph_exported_as_dot() {
+        let cfg = ControlFlowGraph::new(&"nop +0\njmp +2\nacc +1".parse().unwrap());
+        assert_eq!(cfg.to_dot(), concat!(
+            "digraph cfg {\n",
+            "    node [shape=box, fontname=monospace];\n",
+            "    L0 [label=\"L0:\\l0  nop +0\\l1  jmp +2\\l\"];\n",
+            "    L1 [label=\"L1:\\l2  acc +1\\l\", style=dashed];\n",
+            "    L0 -> \"end\";\n",
+            "    L1 -> \"end\";\n",
+            "}\n",
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::handheld_halting::Instruction;
+
+// Which single-instruction edits the analyser may try.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Edits {
+    // Swap a `nop` for a `jmp` or a `jmp` for a `nop`.
+    Jumps,
+    // As `Jumps`, and also replace an `acc` with a `nop` or `jmp` of the same operand.
+    JumpsAndAcc,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fix {
+    pub index: usize,
+    pub from: Instruction,
+    pub to: Instruction,
+    // The accumulator when the repaired program terminates.
+    pub accumulator: isize,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Diagnosis {
+    // The program already terminates without any edits.
+    Terminates { accumulator: isize },
+    // Every single edit that makes the program terminate, in the order the broken program reaches them.
+    Repairable(Vec<Fix>),
+    NoFix,
+}
+
+// Where the instruction sends the machine next: None for a jump outside the program,
+// and the program's length for a normal end. Registered instructions are only known at
+// run time, so the analysis treats them as never reaching the end.
+fn successor(program: &[Instruction], index: usize, instruction: &Instruction) -> Option<usize> {
+    match *instruction {
+        Instruction::Acc(_) | Instruction::Nop(_) => Some(index + 1),
+        Instruction::Jmp(offset) => index.checked_add_signed(offset).filter(|&next| next <= program.len()),
+        Instruction::Custom(..) => None,
+    }
+}
+
+fn change(instruction: &Instruction) -> isize {
+    match *instruction {
+        Instruction::Acc(change) => change,
+        _ => 0,
+    }
+}
+
+fn alternatives(instruction: &Instruction, edits: Edits) -> Vec<Instruction> {
+    match (instruction, edits) {
+        (&Instruction::Nop(operand), _) => vec![Instruction::Jmp(operand)],
+        (&Instruction::Jmp(operand), _) => vec![Instruction::Nop(operand)],
+        (Instruction::Acc(_), Edits::Jumps) => vec![],
+        (&Instruction::Acc(operand), Edits::JumpsAndAcc) => vec![Instruction::Nop(operand), Instruction::Jmp(operand)],
+        (Instruction::Custom(..), _) => vec![],
+    }
+}
+
+// For every position, the accumulator change between there and a normal end, or None
+// if the unedited program never ends from there or the change does not fit in an
+// `isize`. Found by working back from the end over the reversed edges, so each
+// instruction is visited once.
+fn find_changes_to_end(program: &[Instruction]) -> Vec<Option<isize>> {
+    let mut predecessors = vec![vec![]; program.len() + 1];
+    for (index, instruction) in program.iter().enumerate() {
+        if let Some(next) = successor(program, index, instruction) {
+            predecessors[next].push(index);
+        }
+    }
+    let mut changes_to_end: Vec<Option<isize>> = vec![None; program.len() + 1];
+    changes_to_end[program.len()] = Some(0);
+    let mut pending = vec![program.len()];
+    while let Some(next) = pending.pop() {
+        for &index in &predecessors[next] {
+            changes_to_end[index] = changes_to_end[next].and_then(|rest| rest.checked_add(change(&program[index])));
+            pending.push(index);
+        }
+    }
+    changes_to_end
+}
+
+// Finds every single edit that makes the program end normally in time linear in its length.
+// Only instructions the broken program actually runs can matter. Once it loops or leaves
+// the program, none of the instructions it ran can reach the end, so an edited
+// instruction is never run a second time and the end of the run can be looked up rather
+// than simulated. An edit whose accumulator would overflow is not a fix, and the broken
+// program stops being followed once its own accumulator would overflow.
+pub fn diagnose(program: &[Instruction], edits: Edits) -> Diagnosis {
+    let changes_to_end = find_changes_to_end(program);
+    if let Some(accumulator) = changes_to_end[0] {
+        return Diagnosis::Terminates { accumulator };
+    }
+    let mut seen = vec![false; program.len()];
+    let mut fixes = vec![];
+    let (mut index, mut accumulator) = (0, 0isize);
+    while index < program.len() && !seen[index] {
+        seen[index] = true;
+        let instruction = &program[index];
+        for to in alternatives(instruction, edits) {
+            let rest = successor(program, index, &to).and_then(|next| changes_to_end[next]);
+            let total = rest.and_then(|rest| accumulator.checked_add(change(&to))?.checked_add(rest));
+            if let Some(accumulator) = total {
+                fixes.push(Fix { index, from: instruction.clone(), to, accumulator });
+            }
+        }
+        match accumulator.checked_add(change(instruction)) {
+            Some(total) => accumulator = total,
+            None => break,
+        }
+        match successor(program, index, instruction) {
+            Some(next) => index = next,
+            None => break,
+        }
+    }
+    if fixes.is_empty() { Diagnosis::NoFix } else { Diagnosis::Repairable(fixes) }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::handheld_halting::GameConsole;
+
+    fn program(text: &str) -> Vec<Instruction> {
+        text.lines().map(|line| line.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn single_fix_found_in_example() {
+        let console = GameConsole::new("test_data/test1.txt").unwrap();
+        let fix = Fix { index: 7, from: Instruction::Jmp(-4), to: Instruction::Nop(-4), accumulator: 8 };
+        assert_eq!(diagnose(console.original(), Edits::Jumps), Diagnosis::Repairable(vec![fix.clone()]));
+        assert_eq!(diagnose(console.original(), Edits::JumpsAndAcc), Diagnosis::Repairable(vec![fix]));
+    }
+
+    #[test]
+    fn acc_edits_only_tried_when_asked_for() {
+        let program = program("acc +2\njmp -1\nacc +3");
+        let flip = Fix { index: 1, from: Instruction::Jmp(-1), to: Instruction::Nop(-1), accumulator: 5 };
+        assert_eq!(diagnose(&program, Edits::Jumps), Diagnosis::Repairable(vec![flip.clone()]));
+        assert_eq!(diagnose(&program, Edits::JumpsAndAcc), Diagnosis::Repairable(vec![
+            Fix { index: 0, from: Instruction::Acc(2), to: Instruction::Jmp(2), accumulator: 3 },
+            flip,
+        ]));
+    }
+
+    #[test]
+    fn jumps_out_of_the_program_never_count_as_fixes() {
+        assert_eq!(diagnose(&program("nop +5\njmp -1"), Edits::Jumps), Diagnosis::Repairable(vec![
+            Fix { index: 1, from: Instruction::Jmp(-1), to: Instruction::Nop(-1), accumulator: 0 },
+        ]));
+        assert_eq!(diagnose(&program("acc +1\njmp +5"), Edits::Jumps), Diagnosis::Repairable(vec![
+            Fix { index: 1, from: Instruction::Jmp(5), to: Instruction::Nop(5), accumulator: 1 },
+        ]));
+        assert_eq!(diagnose(&program("jmp -3"), Edits::Jumps), Diagnosis::Repairable(vec![
+            Fix { index: 0, from: Instruction::Jmp(-3), to: Instruction::Nop(-3), accumulator: 0 },
+        ]));
+    }
+
+    #[test]
+    fn working_and_hopeless_programs_reported() {
+        assert_eq!(diagnose(&program("acc +4\nnop +0"), Edits::Jumps), Diagnosis::Terminates { accumulator: 4 });
+        assert_eq!(diagnose(&[], Edits::Jumps), Diagnosis::Terminates { accumulator: 0 });
+        assert_eq!(diagnose(&program("jmp +0\njmp -1"), Edits::JumpsAndAcc), Diagnosis::NoFix);
+    }
+
+    #[test]
+    fn overflowing_accumulators_never_count_as_fixes() {
+        assert_eq!(diagnose(&program("acc +9223372036854775807\nacc +1"), Edits::Jumps), Diagnosis::NoFix);
+        assert_eq!(diagnose(&program("acc +9223372036854775807\njmp +0\nacc +1"), Edits::Jumps), Diagnosis::NoFix);
+        assert_eq!(diagnose(&program("acc +9223372036854775807\nnop +0\nacc +1\njmp -1"), Edits::Jumps), Diagnosis::NoFix);
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use crate::handheld_halting::{GameConsole, Instruction, Outcome, Snapshot};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+    // Stops before the instruction at this index runs.
+    Instruction(usize),
+    // Stops once an instruction changes the accumulator to this value.
+    Accumulator(isize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Instruction(index) => write!(f, "instruction {}", index),
+            Breakpoint::Accumulator(value) => write!(f, "acc = {}", value),
+        }
+    }
+}
+
+// Values shown every time the debugger stops.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Watch {
+    Accumulator,
+    Position,
+    Steps,
+    Instruction(usize),
+    Output,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TraceEntry {
+    pub position: usize,
+    pub instruction: Instruction,
+    // The accumulator after the instruction ran.
+    pub accumulator: isize,
+}
+
+// Why the debugger handed control back.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(Breakpoint),
+    // About to run an instruction for the second time since the last loop stop, with the
+    // same accumulator if the program branches on it, as `GameConsole::run` counts loops.
+    Loop(usize),
+    Terminated,
+    OutOfBounds(isize),
+    IllegalInstruction(usize),
+    Overflow(usize),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Breakpoint(breakpoint) => write!(f, "hit breakpoint at {}", breakpoint),
+            Stop::Loop(position) => write!(f, "loop detected at instruction {}", position),
+            Stop::Terminated => write!(f, "program terminated"),
+            Stop::OutOfBounds(position) => write!(f, "jumped out of the program to {}", position),
+            Stop::IllegalInstruction(position) => write!(f, "illegal instruction at {}", position),
+            Stop::Overflow(position) => write!(f, "overflow at instruction {}", position),
+        }
+    }
+}
+
+pub struct Debugger {
+    console: GameConsole,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Watch>,
+    trace: Vec<TraceEntry>,
+    // Positions run, with the accumulator on arriving there if the program branches on it.
+    visited: HashSet<(usize, isize)>,
+    branches: bool,
+    saved: Option<Snapshot>,
+}
+
+impl Debugger {
+    pub fn new(console: GameConsole) -> Self {
+        let branches = console.branches();
+        Debugger { console, breakpoints: vec![], watches: vec![], trace: vec![], visited: HashSet::new(), branches, saved: None }
+    }
+
+    #[cfg(test)]
+    pub fn console(&self) -> &GameConsole {
+        &self.console
+    }
+
+    // False if the breakpoint was already set.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        if self.breakpoints.contains(&breakpoint) {
+            return false;
+        }
+        self.breakpoints.push(breakpoint);
+        true
+    }
+
+    // False if there was no such breakpoint.
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|&other| other != breakpoint);
+        self.breakpoints.len() < count
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_watch(&mut self, watch: Watch) {
+        if !self.watches.contains(&watch) {
+            self.watches.push(watch);
+        }
+    }
+
+    pub fn watches(&self) -> Vec<(Watch, String)> {
+        self.watches.iter().map(|&watch| (watch, self.evaluate(watch))).collect()
+    }
+
+    fn evaluate(&self, watch: Watch) -> String {
+        match watch {
+            Watch::Accumulator => format!("acc = {}", self.console.accumulator()),
+            Watch::Position => format!("pc = {}", self.console.position()),
+            Watch::Steps => format!("steps = {}", self.trace.len()),
+            Watch::Instruction(index) => match self.console.instruction(index) {
+                Some(instruction) => format!("[{}] = {}", index, instruction),
+                None => format!("[{}] = out of range", index),
+            },
+            Watch::Output => {
+                let output: Vec<String> = self.console.output().iter().map(|value| value.to_string()).collect();
+                format!("out = [{}]", output.join(", "))
+            },
+        }
+    }
+
+    // Swaps in another instruction without touching the original program. None if
+    // there is no instruction at the index.
+    pub fn patch(&mut self, index: usize, instruction: Instruction) -> Option<Instruction> {
+        let before = self.console.patch(index, instruction);
+        self.branches = self.console.branches();
+        before
+    }
+
+    // Each patched instruction with the original it replaces.
+    pub fn patches(&self) -> Vec<(usize, Instruction, Instruction)> {
+        self.console.patches().into_iter()
+            .filter_map(|index| Some((index, self.console.original()[index].clone(), self.console.instruction(index)?)))
+            .collect()
+    }
+
+    pub fn save(&mut self) {
+        self.saved = Some(self.console.snapshot());
+    }
+
+    // False if nothing was saved. The trace is kept, but loops are looked for afresh.
+    pub fn restore(&mut self) -> bool {
+        let Some(snapshot) = &self.saved else {
+            return false;
+        };
+        self.console.restore(snapshot);
+        self.branches = self.console.branches();
+        self.visited.clear();
+        true
+    }
+
+    // Back to the original program from the start, with no patches and an empty trace.
+    pub fn reset(&mut self) {
+        self.console.reset();
+        self.branches = self.console.branches();
+        self.trace.clear();
+        self.visited.clear();
+    }
+
+    // Runs a single instruction and reports whatever the machine ran into next.
+    pub fn step(&mut self) -> Stop {
+        if let Some(stop) = self.halted() {
+            return stop;
+        }
+        let position = self.console.position() as usize;
+        let before = self.console.accumulator();
+        let instruction = match self.console.step() {
+            Ok(instruction) => instruction,
+            Err(Outcome::Overflow(position)) => return Stop::Overflow(position),
+            Err(_) => return Stop::IllegalInstruction(position),
+        };
+        self.visited.insert(self.state(position, before));
+        let accumulator = self.console.accumulator();
+        self.trace.push(TraceEntry { position, instruction, accumulator });
+        if let Some(stop) = self.halted() {
+            return stop;
+        }
+        let position = self.console.position();
+        let hit = self.breakpoints.iter().find(|&&breakpoint| match breakpoint {
+            Breakpoint::Instruction(index) => index as isize == position,
+            Breakpoint::Accumulator(value) => value == accumulator && accumulator != before,
+        });
+        if let Some(&breakpoint) = hit {
+            return Stop::Breakpoint(breakpoint);
+        }
+        if self.visited.contains(&self.state(position as usize, accumulator)) {
+            // Forget the instructions seen so far, so continuing goes round the loop once more.
+            self.visited.clear();
+            return Stop::Loop(position as usize);
+        }
+        Stop::Stepped
+    }
+
+    fn state(&self, position: usize, accumulator: isize) -> (usize, isize) {
+        (position, if self.branches { accumulator } else { 0 })
+    }
+
+    // Steps until anything other than a plain step happens.
+    pub fn resume(&mut self) -> Stop {
+        loop {
+            match self.step() {
+                Stop::Stepped => (),
+                stop => return stop,
+            }
+        }
+    }
+
+    fn halted(&self) -> Option<Stop> {
+        if self.console.is_terminated() {
+            Some(Stop::Terminated)
+        } else if self.console.current_instruction().is_none() {
+            Some(Stop::OutOfBounds(self.console.position()))
+        } else {
+            None
+        }
+    }
+
+    #[cfg(test)]
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    // One line per instruction run: step number, position, instruction and the accumulator afterwards.
+    pub fn write_trace<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (step, entry) in self.trace.iter().enumerate() {
+            writeln!(writer, "{:>6}  {:>4}  {}  acc={}", step + 1, entry.position, entry.instruction, entry.accumulator)?;
+        }
+        Ok(())
+    }
+
+    pub fn dump_trace(&self, filepath: &str) -> io::Result<()> {
+        self.write_trace(&mut File::create(filepath)?)
+    }
+
+    pub fn status(&self) -> String {
+        match self.console.current_instruction() {
+            Some(instruction) => format!("{:>4}: {}  acc={}", self.console.position(), instruction, self.console.accumulator()),
+            None => format!("{:>4}: <end>  acc={}", self.console.position(), self.console.accumulator()),
+        }
+    }
+
+    // Reads commands until `quit` or the end of the input, writing each reply to the output.
+    pub fn run_commands<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> io::Result<()> {
+        writeln!(output, "{}", self.status())?;
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(Command::Quit) => break,
+                Ok(command) => self.run_command(command, output)?,
+                Err(error) => writeln!(output, "{}", error)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn run_command<W: Write>(&mut self, command: Command, output: &mut W) -> io::Result<()> {
+        match command {
+            Command::Step(count) => {
+                let mut stop = Stop::Stepped;
+                for _ in 0..count {
+                    stop = self.step();
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                self.report(stop, output)?;
+            },
+            Command::Continue => {
+                let stop = self.resume();
+                self.report(stop, output)?;
+            },
+            Command::Break(breakpoint) => match self.add_breakpoint(breakpoint) {
+                true => writeln!(output, "breakpoint set at {}", breakpoint)?,
+                false => writeln!(output, "breakpoint already set at {}", breakpoint)?,
+            },
+            Command::Delete(breakpoint) => match self.remove_breakpoint(breakpoint) {
+                true => writeln!(output, "breakpoint removed from {}", breakpoint)?,
+                false => writeln!(output, "no breakpoint at {}", breakpoint)?,
+            },
+            Command::Breakpoints => {
+                if self.breakpoints().is_empty() {
+                    writeln!(output, "no breakpoints")?;
+                }
+                for breakpoint in self.breakpoints() {
+                    writeln!(output, "breakpoint at {}", breakpoint)?;
+                }
+            },
+            Command::Watch(watch) => {
+                self.add_watch(watch);
+                writeln!(output, "watching {}", self.evaluate(watch))?;
+            },
+            Command::Print => writeln!(output, "{}", self.status())?,
+            Command::Patch(index, text) => match self.console.instruction_set().parse(&text) {
+                Ok(instruction) => match self.patch(index, instruction.clone()) {
+                    Some(before) => writeln!(output, "[{}] {} -> {}", index, before, instruction)?,
+                    None => writeln!(output, "[{}] = out of range", index)?,
+                },
+                Err(error) => writeln!(output, "{}", error)?,
+            },
+            Command::Patches => {
+                for (index, from, to) in self.patches() {
+                    writeln!(output, "[{}] {} -> {}", index, from, to)?;
+                }
+            },
+            Command::Save => {
+                self.save();
+                writeln!(output, "saved at {}", self.status())?;
+            },
+            Command::Restore => match self.restore() {
+                true => writeln!(output, "{}", self.status())?,
+                false => writeln!(output, "nothing saved")?,
+            },
+            Command::Reset => {
+                self.reset();
+                writeln!(output, "{}", self.status())?;
+            },
+            Command::Trace(filepath) => match self.dump_trace(&filepath) {
+                Ok(()) => writeln!(output, "{} steps written to {}", self.trace.len(), filepath)?,
+                Err(error) => writeln!(output, "could not write {}: {}", filepath, error)?,
+            },
+            Command::Quit => (),
+        }
+        Ok(())
+    }
+
+    fn report<W: Write>(&self, stop: Stop, output: &mut W) -> io::Result<()> {
+        if stop != Stop::Stepped {
+            writeln!(output, "{}", stop)?;
+        }
+        writeln!(output, "{}", self.status())?;
+        for (_, value) in self.watches() {
+            writeln!(output, "  {}", value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Command {
+    Step(usize),
+    Continue,
+    Break(Breakpoint),
+    Delete(Breakpoint),
+    Breakpoints,
+    Watch(Watch),
+    Print,
+    // The index and the instruction to put there, parsed by the console's instruction set.
+    Patch(usize, String),
+    Patches,
+    Save,
+    Restore,
+    Reset,
+    Trace(String),
+    Quit,
+}
+
+// Commands as typed at the debugger prompt, e.g. "step 5", "break 7", "break acc 12",
+// "watch acc", "patch 7 nop -4" or "trace out.txt". Most have a one-letter short form.
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let not_a_number = |word: &str| format!("expected a number, found \"{}\"", word);
+        let number = |word: &str| word.parse().map_err(|_| not_a_number(word));
+        // Indices and counts cannot be negative, so one that is gets the usage message.
+        let count = |word: &str, usage: &str| match word.parse() {
+            Ok(count) => Ok(count),
+            Err(_) if word.parse::<isize>().is_ok() => Err(usage.to_string()),
+            Err(_) => Err(not_a_number(word)),
+        };
+        let breakpoint_usage = "usage: break <index> | break acc <value>";
+        let breakpoint = |words: &[&str]| match words {
+            [index] => Ok(Breakpoint::Instruction(count(index, breakpoint_usage)?)),
+            ["acc", value] => Ok(Breakpoint::Accumulator(number(value)?)),
+            _ => Err(breakpoint_usage.to_string()),
+        };
+        match words[..] {
+            ["s" | "step"] => Ok(Command::Step(1)),
+            ["s" | "step", steps] => Ok(Command::Step(count(steps, "usage: step [<count>]")?)),
+            ["c" | "continue"] => Ok(Command::Continue),
+            ["b" | "break", ref rest @ ..] => Ok(Command::Break(breakpoint(rest)?)),
+            ["d" | "delete", ref rest @ ..] => Ok(Command::Delete(breakpoint(rest)?)),
+            ["breakpoints"] => Ok(Command::Breakpoints),
+            ["w" | "watch", "acc"] => Ok(Command::Watch(Watch::Accumulator)),
+            ["w" | "watch", "pc"] => Ok(Command::Watch(Watch::Position)),
+            ["w" | "watch", "steps"] => Ok(Command::Watch(Watch::Steps)),
+            ["w" | "watch", "out"] => Ok(Command::Watch(Watch::Output)),
+            ["w" | "watch", index] => Ok(Command::Watch(Watch::Instruction(count(index, "usage: watch acc | pc | steps | out | <index>")?))),
+            ["p" | "print"] => Ok(Command::Print),
+            ["patch", index, ref rest @ ..] if !rest.is_empty() => Ok(Command::Patch(count(index, "usage: patch <index> <instruction>")?, rest.join(" "))),
+            ["patch", ..] => Err("usage: patch <index> <instruction>".to_string()),
+            ["patches"] => Ok(Command::Patches),
+            ["save"] => Ok(Command::Save),
+            ["restore"] => Ok(Command::Restore),
+            ["reset"] => Ok(Command::Reset),
+            ["t" | "trace", filepath] => Ok(Command::Trace(filepath.to_string())),
+            ["q" | "quit"] => Ok(Command::Quit),
+            _ => Err(format!("unknown command \"{}\"", line.trim())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::handheld_halting::Limits;
+    use crate::instruction_set::InstructionSet;
+
+    fn debugger() -> Debugger {
+        Debugger::new(GameConsole::new("test_data/test1.txt").unwrap())
+    }
+
+    #[test]
+    fn stepping_records_a_trace() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step(), Stop::Stepped);
+        assert_eq!(debugger.step(), Stop::Stepped);
+        assert_eq!(debugger.trace(), &[
+            TraceEntry { position: 0, instruction: Instruction::Nop(0), accumulator: 0 },
+            TraceEntry { position: 1, instruction: Instruction::Acc(1), accumulator: 1 },
+        ]);
+        assert_eq!(debugger.status(), "   2: jmp +4  acc=1");
+        let mut output = vec![];
+        debugger.write_trace(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "     1     0  nop +0  acc=0\n     2     1  acc +1  acc=1\n");
+    }
+
+    #[test]
+    fn continuing_stops_at_breakpoints_and_loops() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(Breakpoint::Instruction(4));
+        assert!(!debugger.add_breakpoint(Breakpoint::Instruction(4)));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(Breakpoint::Instruction(4)));
+        assert_eq!(debugger.console().accumulator(), 5);
+        assert_eq!(debugger.resume(), Stop::Loop(1));
+        assert_eq!(debugger.console().accumulator(), 5);
+        assert!(debugger.remove_breakpoint(Breakpoint::Instruction(4)));
+        debugger.add_breakpoint(Breakpoint::Accumulator(7));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(Breakpoint::Accumulator(7)));
+        assert_eq!(debugger.console().position(), 7);
+    }
+
+    #[test]
+    fn continuing_stops_when_the_program_ends() {
+        let mut debugger = Debugger::new(GameConsole::new("test_data/test3.txt").unwrap());
+        debugger.add_watch(Watch::Steps);
+        debugger.add_watch(Watch::Instruction(7));
+        assert_eq!(debugger.resume(), Stop::Terminated);
+        assert_eq!(debugger.step(), Stop::Terminated);
+        assert_eq!(debugger.status(), "   9: <end>  acc=8");
+        assert_eq!(debugger.watches(), vec![
+            (Watch::Steps, "steps = 6".to_string()),
+            (Watch::Instruction(7), "[7] = nop -4".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn registered_instructions_treated_as_the_console_treats_them() {
+        let console = GameConsole::parse("acc +2\nmul +3\nacc +0", InstructionSet::extended()).unwrap();
+        let mut debugger = Debugger::new(console);
+        debugger.add_breakpoint(Breakpoint::Accumulator(6));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(Breakpoint::Accumulator(6)));
+        assert_eq!(debugger.console().position(), 2);
+        let console = GameConsole::parse("acc +3\nacc -1\njnz -1\njmp -1", InstructionSet::extended()).unwrap();
+        let mut debugger = Debugger::new(console);
+        assert_eq!(debugger.resume(), Stop::Loop(2));
+        assert_eq!(debugger.trace().len(), 8);
+        let mut console = GameConsole::parse("acc +3\nacc -1\njnz -1\njmp -1", InstructionSet::extended()).unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::LoopDetected(2, 0));
+    }
+
+    #[test]
+    fn overflow_stops_the_debugger() {
+        let mut debugger = Debugger::new("acc +9223372036854775807\nacc +1".parse().unwrap());
+        assert_eq!(debugger.resume(), Stop::Overflow(1));
+        assert_eq!(debugger.step(), Stop::Overflow(1));
+        assert_eq!(debugger.status(), "   1: acc +1  acc=9223372036854775807");
+    }
+
+    #[test]
+    fn commands_parsed_with_short_forms() {
+        assert_eq!("s".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 5".parse(), Ok(Command::Step(5)));
+        assert_eq!("b 7".parse(), Ok(Command::Break(Breakpoint::Instruction(7))));
+        assert_eq!("break acc -3".parse(), Ok(Command::Break(Breakpoint::Accumulator(-3))));
+        assert_eq!("watch pc".parse(), Ok(Command::Watch(Watch::Position)));
+        assert_eq!("w 3".parse(), Ok(Command::Watch(Watch::Instruction(3))));
+        assert_eq!("t trace.txt".parse(), Ok(Command::Trace("trace.txt".to_string())));
+        assert_eq!("patch 7 nop -4".parse(), Ok(Command::Patch(7, "nop -4".to_string())));
+        assert_eq!("patch 7".parse::<Command>(), Err("usage: patch <index> <instruction>".to_string()));
+        assert_eq!("break x".parse::<Command>(), Err("expected a number, found \"x\"".to_string()));
+        assert_eq!("break -1".parse::<Command>(), Err("usage: break <index> | break acc <value>".to_string()));
+        assert_eq!("step -5".parse::<Command>(), Err("usage: step [<count>]".to_string()));
+        assert_eq!("w -1".parse::<Command>(), Err("usage: watch acc | pc | steps | out | <index>".to_string()));
+        assert_eq!("patch -1 nop +0".parse::<Command>(), Err("usage: patch <index> <instruction>".to_string()));
+        assert_eq!("step x".parse::<Command>(), Err("expected a number, found \"x\"".to_string()));
+        assert_eq!("jump".parse::<Command>(), Err("unknown command \"jump\"".to_string()));
+    }
+
+    #[test]
+    fn interactive_session_driven_by_commands() {
+        let mut debugger = debugger();
+        let mut output = vec![];
+        debugger.run_commands("breakpoints\nwatch acc\nbreak 3\nbreak acc 99\nbreakpoints\ncontinue\nstep 2\nprint\nfly\nquit\nstep\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "   0: nop +0  acc=0\n",
+            "no breakpoints\n",
+            "watching acc = 0\n",
+            "breakpoint set at instruction 3\n",
+            "breakpoint set at acc = 99\n",
+            "breakpoint at instruction 3\n",
+            "breakpoint at acc = 99\n",
+            "hit breakpoint at instruction 3\n",
+            "   3: acc +3  acc=2\n",
+            "  acc = 2\n",
+            "loop detected at instruction 1\n",
+            "   1: acc +1  acc=5\n",
+            "  acc = 5\n",
+            "   1: acc +1  acc=5\n",
+            "unknown command \"fly\"\n",
+        ));
+    }
+
+    #[test]
+    fn program_patched_saved_and_restored() {
+        let mut debugger = debugger();
+        let mut output = vec![];
+        debugger.run_commands("step 2\nsave\npatch 7 nop -4\npatch 9 nop +0\npatch 1 hcf +0\npatches\ncontinue\nrestore\nreset\npatches\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "   0: nop +0  acc=0\n",
+            "   2: jmp +4  acc=1\n",
+            "saved at    2: jmp +4  acc=1\n",
+            "[7] jmp -4 -> nop -4\n",
+            "[9] = out of range\n",
+            "unknown opcode \"hcf\"\n",
+            "[7] jmp -4 -> nop -4\n",
+            "program terminated\n",
+            "   9: <end>  acc=8\n",
+            "   2: jmp +4  acc=1\n",
+            "   0: nop +0  acc=0\n",
+        ));
+        assert_eq!(debugger.trace(), &[]);
+    }
+
+    #[test]
+    fn output_watched_while_running() {
+        let console = GameConsole::parse("acc +2\nout +0\nacc -1\nout +0", InstructionSet::extended()).unwrap();
+        let mut debugger = Debugger::new(console);
+        debugger.add_watch(Watch::Output);
+        assert_eq!(debugger.resume(), Stop::Terminated);
+        assert_eq!(debugger.watches(), vec![(Watch::Output, "out = [2, 1]".to_string())]);
+    }
+}
@@ -1,123 +1,395 @@
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::boot_repair::{diagnose, Diagnosis, Edits};
+use crate::instruction_set::InstructionSet;
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Instruction {
+    Acc(isize),
+    Jmp(isize),
+    Nop(isize),
+    // An opcode registered in an `InstructionSet`, sharing the set's copy of the name.
+    Custom(Rc<str>, isize),
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum InstructionError {
+    MissingOperand,
+    UnknownOpcode(String),
+    InvalidOperand(String),
+    UnexpectedText(String),
+}
+
+thread_local! {
+    static BUILT_IN_ONLY: InstructionSet = InstructionSet::default();
+}
+
+// Only `acc`, `jmp` and `nop`; use an `InstructionSet` to parse anything else.
+impl FromStr for Instruction {
+    type Err = InstructionError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        BUILT_IN_ONLY.with(|instruction_set| instruction_set.parse(text))
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Acc(operand) => write!(f, "acc {:+}", operand),
+            Instruction::Jmp(operand) => write!(f, "jmp {:+}", operand),
+            Instruction::Nop(operand) => write!(f, "nop {:+}", operand),
+            Instruction::Custom(name, operand) => write!(f, "{} {:+}", name, operand),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: InstructionError,
+}
+
+impl fmt::Display for InstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstructionError::MissingOperand => write!(f, "missing operand"),
+            InstructionError::UnknownOpcode(opcode) => write!(f, "unknown opcode \"{}\"", opcode),
+            InstructionError::InvalidOperand(operand) => write!(f, "invalid operand \"{}\"", operand),
+            InstructionError::UnexpectedText(text) => write!(f, "unexpected \"{}\" after operand", text),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "{}", error),
+            ReadError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+// The running program is the original, which is never copied, with any patched
+// instructions laid over it.
 pub struct GameConsole {
-    instructions: Vec<String>,
+    original: Rc<Vec<Instruction>>,
+    patches: BTreeMap<usize, Instruction>,
+    instruction_set: Rc<InstructionSet>,
+    accumulator: isize,
+    // Signed, as a jump can land before the start of the program.
+    position: isize,
+    output: Vec<isize>,
+}
+
+// The whole machine state at one moment; cheap to take, as only the patches are copied.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    patches: BTreeMap<usize, Instruction>,
     accumulator: isize,
-    position: usize,
-    filepath: String,
+    position: isize,
+    output: Vec<isize>,
+}
+
+// How long `run` may go on for; no limits by default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Limits {
+    pub steps: Option<usize>,
+    pub time: Option<Duration>,
+}
+
+// How a run ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Terminated,
+    // The position about to be run a second time and the accumulator at that point.
+    LoopDetected(usize, isize),
+    // A jump landed somewhere other than an instruction or just past the last one.
+    OutOfBounds(isize),
+    StepLimit,
+    TimeLimit,
+    // An instruction the console's instruction set does not know, or one whose handler halted the machine.
+    IllegalInstruction(usize),
+    // An instruction that would take the accumulator or the position past what an `isize` holds.
+    Overflow(usize),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Terminated => write!(f, "program terminated"),
+            Outcome::LoopDetected(position, accumulator) => write!(f, "loop detected at instruction {} with acc={}", position, accumulator),
+            Outcome::OutOfBounds(position) => write!(f, "jumped out of the program to {}", position),
+            Outcome::StepLimit => write!(f, "ran out of steps"),
+            Outcome::TimeLimit => write!(f, "ran out of time"),
+            Outcome::IllegalInstruction(position) => write!(f, "illegal instruction at {}", position),
+            Outcome::Overflow(position) => write!(f, "overflow at instruction {}", position),
+        }
+    }
 }
 
 impl GameConsole {
-    pub fn new(filepath: &str) -> Self {
-        let mut instructions = Vec::<String>::new();
-        load_instructions_from(filepath, &mut instructions);
-        Self { 
-            instructions,
+    pub fn new(filepath: &str) -> Result<Self, ReadError> {
+        GameConsole::load(filepath, InstructionSet::default())
+    }
+
+    pub fn load(filepath: &str, instruction_set: InstructionSet) -> Result<Self, ReadError> {
+        let instructions = load_instructions_from(filepath, &instruction_set)?;
+        Ok(GameConsole::from_instructions(instructions).with_instruction_set(instruction_set))
+    }
+
+    pub fn parse(text: &str, instruction_set: InstructionSet) -> Result<Self, ParseError> {
+        let instructions = parse_instructions(text.lines(), &instruction_set)?;
+        Ok(GameConsole::from_instructions(instructions).with_instruction_set(instruction_set))
+    }
+
+    pub fn from_instructions(instructions: Vec<Instruction>) -> Self {
+        Self {
+            original: Rc::new(instructions),
+            patches: BTreeMap::new(),
+            instruction_set: Rc::new(InstructionSet::default()),
             accumulator: 0,
             position: 0,
-            filepath: filepath.to_string()
+            output: vec![],
         }
     }
 
-    fn acc(&mut self, index: usize) {
-        let change: isize = self.instructions[index][4..].parse().unwrap();
-        self.accumulator += change;
-        self.position += 1;
+    pub fn with_instruction_set(mut self, instruction_set: InstructionSet) -> Self {
+        self.instruction_set = Rc::new(instruction_set);
+        self
     }
 
-    fn jmp(&mut self, index: usize) {
-        let change: isize = self.instructions[index][4..].parse().unwrap();
-        self.position = (change + index as isize) as usize;
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.instruction_set
     }
 
-    fn nop(&mut self, _index: usize) {
-        self.position += 1;
+    pub fn original(&self) -> &[Instruction] {
+        &self.original
     }
 
-    fn operate(&mut self, index: usize) {
-        let operation = &self.instructions[index][0..3];
-        match operation {
-            "acc" => self.acc(index),
-            "jmp" => self.jmp(index),
-            _ => self.nop(index),
-        }
+    // Indices of the instructions that differ from the original program.
+    pub fn patches(&self) -> Vec<usize> {
+        self.patches.keys().copied().collect()
     }
 
-    fn next(&mut self) {
-        self.operate(self.position);
+    // Returns the instruction it replaced, or None, patching nothing, if there is no
+    // instruction at the index. Patching an instruction back to the original removes the patch.
+    pub fn patch(&mut self, index: usize, instruction: Instruction) -> Option<Instruction> {
+        let before = self.instruction(index)?;
+        if self.original[index] == instruction {
+            self.patches.remove(&index);
+        } else {
+            self.patches.insert(index, instruction);
+        }
+        Some(before)
     }
 
-    pub fn find_accumulator_before_loop(&mut self) -> isize {
-        let mut visited_indices = HashSet::<usize>::new();
-        while !visited_indices.contains(&self.position) {
-            visited_indices.insert(self.position);
-            self.next();
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            patches: self.patches.clone(),
+            accumulator: self.accumulator,
+            position: self.position,
+            output: self.output.clone(),
         }
-        self.accumulator
     }
 
-    pub fn accumulator_value_when_terminating_normally(&mut self) -> isize {
-        let wrong_instruction_index = self.find_index_of_wrong_instruction();
-        self.reset();
-        self.update_instruction(wrong_instruction_index);
-        while self.position < self.instructions.len() {
-            self.next();
-        }
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.patches = snapshot.patches.clone();
+        self.accumulator = snapshot.accumulator;
+        self.position = snapshot.position;
+        self.output = snapshot.output.clone();
+    }
+
+    pub fn accumulator(&self) -> isize {
         self.accumulator
     }
 
-    fn is_loop(&mut self) -> bool {
-        let mut visited_indices = HashSet::<usize>::new();
-        while !visited_indices.contains(&self.position) {
-            visited_indices.insert(self.position);
-            self.next();
-            if self.position == self.instructions.len() {
-                return false
+    pub fn position(&self) -> isize {
+        self.position
+    }
+
+    // Every value output so far, oldest first.
+    pub fn output(&self) -> &[isize] {
+        &self.output
+    }
+
+    // The program as it stands, patches included.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        (0..self.original.len()).filter_map(|index| self.instruction(index)).collect()
+    }
+
+    pub fn instruction(&self, index: usize) -> Option<Instruction> {
+        self.patches.get(&index).or_else(|| self.original.get(index)).cloned()
+    }
+
+    // The instruction about to run, or None once the position has left the program.
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.index().and_then(|index| self.instruction(index))
+    }
+
+    fn index(&self) -> Option<usize> {
+        usize::try_from(self.position).ok()
+    }
+
+    // Whether the program, patches included, has an instruction whose jump depends on the
+    // accumulator, so that revisiting an instruction is only a loop with the same accumulator.
+    pub fn branches(&self) -> bool {
+        self.instructions().iter().any(|instruction| self.instruction_set.branches(instruction))
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.index() == Some(self.original.len())
+    }
+
+    // Runs the current instruction and returns it. Does nothing outside the program or if
+    // the instruction cannot run, and says why instead.
+    pub fn step(&mut self) -> Result<Instruction, Outcome> {
+        let Some(instruction) = self.current_instruction() else {
+            return Err(if self.is_terminated() { Outcome::Terminated } else { Outcome::OutOfBounds(self.position) });
+        };
+        self.operate(self.position as usize)?;
+        Ok(instruction)
+    }
+
+    // None, leaving the machine as it was, on overflow.
+    fn acc(&mut self, change: isize) -> Option<()> {
+        self.accumulator = self.accumulator.checked_add(change)?;
+        self.position += 1;
+        Some(())
+    }
+
+    fn jmp(&mut self, index: usize, offset: isize) -> Option<()> {
+        self.position = (index as isize).checked_add(offset)?;
+        Some(())
+    }
+
+    fn nop(&mut self) -> Option<()> {
+        self.position += 1;
+        Some(())
+    }
+
+    // Leaves the machine as it was if the instruction is illegal, there is none, or it overflows.
+    fn operate(&mut self, index: usize) -> Result<(), Outcome> {
+        let Some(instruction) = self.instruction(index) else {
+            return Err(Outcome::IllegalInstruction(index));
+        };
+        let done = match instruction {
+            Instruction::Acc(change) => self.acc(change),
+            Instruction::Jmp(offset) => self.jmp(index, offset),
+            Instruction::Nop(_) => self.nop(),
+            Instruction::Custom(name, operand) => {
+                let effect = self.instruction_set.execute(&name, operand, self.accumulator)
+                    .ok_or(Outcome::IllegalInstruction(index))?;
+                self.jmp(index, effect.jump).map(|()| {
+                    self.accumulator = effect.accumulator;
+                    self.output.extend(effect.output);
+                })
+            },
+        };
+        done.ok_or(Outcome::Overflow(index))
+    }
+
+    // Runs from the current state until the program ends, loops, leaves the program or
+    // hits a limit. A loop is an instruction about to run a second time during this
+    // call, or, if the program branches on the accumulator, an instruction about to
+    // run a second time with the same accumulator.
+    pub fn run(&mut self, limits: &Limits) -> Outcome {
+        let started = Instant::now();
+        let branches = self.branches();
+        let mut visited = HashSet::<(usize, isize)>::new();
+        let mut steps = 0;
+        loop {
+            if self.is_terminated() {
+                return Outcome::Terminated;
+            }
+            let Some(index) = self.index().filter(|&index| index < self.original.len()) else {
+                return Outcome::OutOfBounds(self.position);
+            };
+            let state = (index, if branches { self.accumulator } else { 0 });
+            if !visited.insert(state) {
+                return Outcome::LoopDetected(index, self.accumulator);
+            }
+            if limits.steps.is_some_and(|limit| steps >= limit) {
+                return Outcome::StepLimit;
             }
+            if limits.time.is_some_and(|limit| started.elapsed() >= limit) {
+                return Outcome::TimeLimit;
+            }
+            if let Err(outcome) = self.operate(index) {
+                return outcome;
+            }
+            steps += 1;
+        }
+    }
+
+    // None if the program ends some other way than by looping.
+    pub fn find_accumulator_before_loop(&mut self) -> Option<isize> {
+        match self.run(&Limits::default()) {
+            Outcome::LoopDetected(_, accumulator) => Some(accumulator),
+            _ => None,
         }
-        true
     }
 
-    fn update_instruction(&mut self, index: usize) {
-        let operation = &self.instructions[index][0..3];
-        let operand = &self.instructions[index][3..];
-        match operation {
-            "nop" => self.instructions[index] = "jmp".to_owned() + operand,
-            "jmp" => self.instructions[index] = "nop".to_owned() + operand,
-            _ => {},
+    // The accumulator once the original program, fixed if need be, ends; None if no single fix works.
+    pub fn accumulator_value_when_terminating_normally(&self) -> Option<isize> {
+        match diagnose(&self.original, Edits::Jumps) {
+            Diagnosis::Terminates { accumulator } => Some(accumulator),
+            Diagnosis::Repairable(fixes) => Some(fixes[0].accumulator),
+            Diagnosis::NoFix => None,
         }
     }
 
-    fn reset(&mut self) {
-        load_instructions_from(&self.filepath, &mut self.instructions);
+    // Back to the original program, with nothing patched.
+    pub fn reset(&mut self) {
+        self.patches.clear();
         self.accumulator = 0;
         self.position = 0;
+        self.output.clear();
     }
+}
 
-    fn find_index_of_wrong_instruction(&mut self) -> usize {
-        for index in 0..self.instructions.len() {
-            self.reset();
-            self.update_instruction(index);
-            if let false = self.is_loop() {
-                return index;
-            }
-        }
-        0
+impl FromStr for GameConsole {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        GameConsole::parse(text, InstructionSet::default())
     }
 }
 
-fn load_instructions_from(filepath: &str, instructions : &mut Vec<String>) {
-    instructions.clear();
-    if let Ok(lines) = read_lines(filepath) {
-        for line in lines {
-            if let Ok(instruction) = line {
-                instructions.push(instruction);
-            }
+fn load_instructions_from(filepath: &str, instruction_set: &InstructionSet) -> Result<Vec<Instruction>, ReadError> {
+    let lines = read_lines(filepath).and_then(|lines| lines.collect::<io::Result<Vec<String>>>()).map_err(ReadError::Io)?;
+    parse_instructions(lines.iter(), instruction_set).map_err(ReadError::Parse)
+}
+
+// One instruction per line; blank lines are skipped but still counted for error positions.
+fn parse_instructions<S: AsRef<str>>(lines: impl Iterator<Item = S>, instruction_set: &InstructionSet) -> Result<Vec<Instruction>, ParseError> {
+    let mut instructions = vec![];
+    for (index, line) in lines.enumerate() {
+        let line = line.as_ref();
+        if line.trim().is_empty() {
+            continue;
         }
+        instructions.push(instruction_set.parse(line).map_err(|kind| ParseError { line: index + 1, kind })?);
     }
+    Ok(instructions)
 }
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -132,90 +404,208 @@ mod tests {
 
     #[test]
     fn test_instructions_correctly_read_in() {
-        let console = GameConsole::new("test_data/test1.txt");
-        assert_eq!(9, console.instructions.len());
+        let console = GameConsole::new("test_data/test1.txt").unwrap();
+        assert_eq!(9, console.instructions().len());
     }
 
     #[test]
     fn test_acc_works_correctly() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        console.acc(1);
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        console.acc(1).unwrap();
         assert_eq!(1, console.accumulator);
     }
 
     #[test]
     fn test_jmp_works_correctly() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        console.jmp(2);
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        console.jmp(2, 4).unwrap();
         assert_eq!(6, console.position);
     }
 
     #[test]
     fn correctly_identify_operation() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        console.operate(1);
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        console.operate(1).unwrap();
         assert_eq!(1, console.accumulator);
-        console.operate(2);
+        console.operate(2).unwrap();
         assert_eq!(6, console.position);
     }
 
     #[test]
     fn instructions_increments_correctly() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        console.next();
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        console.step().unwrap();
         assert_eq!(0, console.accumulator);
         assert_eq!(1, console.position);
-        console.next();
+        console.step().unwrap();
         assert_eq!(1, console.accumulator);
         assert_eq!(2, console.position);
-        console.next();
+        console.step().unwrap();
         assert_eq!(1, console.accumulator);
         assert_eq!(6, console.position);
     }
 
     #[test]
     fn correctly_finds_accumulator_before_looping_back() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        assert_eq!(5, console.find_accumulator_before_loop());
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        assert_eq!(Some(5), console.find_accumulator_before_loop());
+        let mut console = GameConsole::new("test_data/test3.txt").unwrap();
+        assert_eq!(None, console.find_accumulator_before_loop());
     }
 
     #[test]
     fn find_correct_accumulator_value_when_terminating_normally() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        assert_eq!(8, console.accumulator_value_when_terminating_normally());
-    }
-
-    #[test]
-    fn determine_if_instructions_are_a_loop() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        assert!(console.is_loop());
-        console.update_instruction(7);
-        assert_eq!(false, console.is_loop());
+        let console = GameConsole::new("test_data/test1.txt").unwrap();
+        assert_eq!(Some(8), console.accumulator_value_when_terminating_normally());
     }
 
     #[test]
-    fn new_console_created_from_new_instruction() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        console.update_instruction(0);
-        assert_eq!("jmp +0", console.instructions[0]);
-        console.update_instruction(2);
-        assert_eq!("nop +4", console.instructions[2]);
+    fn patched_program_terminates() {
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::LoopDetected(1, 5));
+        console.reset();
+        console.patch(7, Instruction::Nop(-4));
+        assert_eq!(console.run(&Limits::default()), Outcome::Terminated);
+        assert_eq!(Some(Instruction::Nop(-4)), console.instruction(7));
     }
 
     #[test]
     fn console_can_be_reset() {
-        let mut console = GameConsole::new("test_data/test1.txt");
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
         console.find_accumulator_before_loop();
-        console.update_instruction(0);
+        console.patch(0, Instruction::Jmp(0));
         console.reset();
         assert_eq!(0, console.accumulator);
         assert_eq!(0, console.position);
-        assert_eq!("nop +0", console.instructions[0]);
+        assert_eq!(Some(Instruction::Nop(0)), console.instruction(0));
     }
 
     #[test]
-    fn find_index_of_the_wrong_instruction() {
-        let mut console = GameConsole::new("test_data/test1.txt");
-        assert_eq!(7, console.find_index_of_wrong_instruction());
+    fn no_accumulator_when_no_single_fix_works() {
+        let console: GameConsole = "jmp +0\njmp -1".parse().unwrap();
+        assert_eq!(None, console.accumulator_value_when_terminating_normally());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn instructions_decoded_with_signed_operands() {
+        assert_eq!("acc +7".parse(), Ok(Instruction::Acc(7)));
+        assert_eq!("jmp -3".parse(), Ok(Instruction::Jmp(-3)));
+        assert_eq!("  nop   0 ".parse(), Ok(Instruction::Nop(0)));
+        assert_eq!(Instruction::Jmp(-3).to_string(), "jmp -3");
+        assert_eq!(Instruction::Nop(0).to_string(), "nop +0");
+    }
+
+    #[test]
+    fn malformed_instructions_rejected() {
+        assert_eq!("hcf +1".parse::<Instruction>(), Err(InstructionError::UnknownOpcode("hcf".to_string())));
+        assert_eq!("acc".parse::<Instruction>(), Err(InstructionError::MissingOperand));
+        assert_eq!("acc +x".parse::<Instruction>(), Err(InstructionError::InvalidOperand("+x".to_string())));
+        assert_eq!("acc +1 +2".parse::<Instruction>(), Err(InstructionError::UnexpectedText("+2".to_string())));
+    }
+
+    #[test]
+    fn bad_lines_reported_with_line_numbers() {
+        let error = load_instructions_from("test_data/test2.txt", &InstructionSet::default()).unwrap_err();
+        assert_eq!(error.to_string(), "line 4: unknown opcode \"mul\"");
+        let ReadError::Parse(error) = error else { panic!("expected a parse error") };
+        assert_eq!(error, ParseError { line: 4, kind: InstructionError::UnknownOpcode("mul".to_string()) });
+        assert!(matches!(GameConsole::new("test_data/missing.txt"), Err(ReadError::Io(_))));
+    }
+
+    #[test]
+    fn console_stepped_one_instruction_at_a_time() {
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        assert_eq!(console.step(), Ok(Instruction::Nop(0)));
+        assert_eq!(console.step(), Ok(Instruction::Acc(1)));
+        assert_eq!((console.position(), console.accumulator()), (2, 1));
+        assert_eq!(console.current_instruction(), Some(Instruction::Jmp(4)));
+        console.patch(7, Instruction::Nop(-4));
+        while console.step().is_ok() {}
+        assert!(console.is_terminated());
+        assert_eq!(console.accumulator(), 8);
+    }
+
+    #[test]
+    fn console_built_from_text_without_a_file() {
+        let console: GameConsole = "nop +0\n\nacc -2\n".parse().unwrap();
+        assert_eq!(console.instructions(), vec![Instruction::Nop(0), Instruction::Acc(-2)]);
+        let error = "nop +0\n\nacc two".parse::<GameConsole>().err().unwrap();
+        assert_eq!(error.to_string(), "line 3: invalid operand \"two\"");
+    }
+
+    #[test]
+    fn patches_laid_over_the_original() {
+        let mut console = GameConsole::from_instructions(vec![Instruction::Nop(1), Instruction::Acc(1)]);
+        console.patch(0, Instruction::Nop(1));
+        assert_eq!(console.patches(), vec![]);
+        console.patch(0, Instruction::Jmp(1));
+        assert_eq!(console.patches(), vec![0]);
+        assert_eq!(console.instructions(), vec![Instruction::Jmp(1), Instruction::Acc(1)]);
+        assert_eq!(console.original()[0], Instruction::Nop(1));
+        console.patch(0, Instruction::Nop(1));
+        assert_eq!(console.patches(), vec![]);
+        assert_eq!(console.patch(1, Instruction::Nop(1)), Some(Instruction::Acc(1)));
+        assert_eq!(console.patch(2, Instruction::Nop(1)), None);
+        assert_eq!(console.patches(), vec![1]);
+        console.reset();
+        assert_eq!(console.patches(), vec![]);
+    }
+
+    #[test]
+    fn machine_state_restored_from_snapshot() {
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        console.step().unwrap();
+        console.step().unwrap();
+        let snapshot = console.snapshot();
+        console.patch(2, Instruction::Nop(4));
+        console.find_accumulator_before_loop();
+        assert_ne!(console.snapshot(), snapshot);
+        console.restore(&snapshot);
+        assert_eq!((console.position(), console.accumulator()), (2, 1));
+        assert_eq!(console.current_instruction(), Some(Instruction::Jmp(4)));
+        assert_eq!(console.patches(), vec![]);
+    }
+
+    #[test]
+    fn runs_end_with_typed_outcomes() {
+        let mut console = GameConsole::new("test_data/test1.txt").unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::LoopDetected(1, 5));
+        console.reset();
+        assert_eq!(console.run(&Limits { steps: Some(3), time: None }), Outcome::StepLimit);
+        assert_eq!(console.position(), 6);
+        assert_eq!(console.run(&Limits { steps: None, time: Some(Duration::ZERO) }), Outcome::TimeLimit);
+        let mut console = GameConsole::new("test_data/test3.txt").unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::Terminated);
+        let mut console: GameConsole = "acc +1\njmp -2".parse().unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::OutOfBounds(-1));
+        assert_eq!(console.step(), Err(Outcome::OutOfBounds(-1)));
+    }
+
+    #[test]
+    fn overflowing_instructions_stop_the_run() {
+        let mut console: GameConsole = "acc +9223372036854775807\nacc +1".parse().unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::Overflow(1));
+        assert_eq!((console.position(), console.accumulator()), (1, isize::MAX));
+        let mut console: GameConsole = "nop +0\njmp +9223372036854775807".parse().unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::Overflow(1));
+        assert_eq!(console.step(), Err(Outcome::Overflow(1)));
+        let mut console = GameConsole::parse("acc -1\njnz +9223372036854775807", InstructionSet::extended()).unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::Overflow(1));
+        assert_eq!(console.accumulator(), -1);
+    }
+
+    #[test]
+    fn registered_instructions_run_on_the_console() {
+        let program = "acc +3\nout +0\nacc -1\njnz -2\nacc +2\nmul +5\nout +0";
+        let mut console = GameConsole::parse(program, InstructionSet::extended()).unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::Terminated);
+        assert_eq!(console.output(), &[3, 2, 1, 10]);
+        let mut console = GameConsole::parse("acc +1\njnz +0", InstructionSet::extended()).unwrap();
+        assert_eq!(console.run(&Limits::default()), Outcome::LoopDetected(1, 1));
+        assert_eq!(Outcome::LoopDetected(1, 1).to_string(), "loop detected at instruction 1 with acc=1");
+        let mut console = GameConsole::from_instructions(vec![Instruction::Custom("mul".into(), 2)]);
+        assert_eq!(console.run(&Limits::default()), Outcome::IllegalInstruction(0));
+        assert_eq!(console.step(), Err(Outcome::IllegalInstruction(0)));
+        assert_eq!(console.position(), 0);
+    }
+}
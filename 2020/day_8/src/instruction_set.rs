@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::handheld_halting::{Instruction, InstructionError};
+
+// What a registered instruction did: the accumulator afterwards, how far to move
+// (1 for the next instruction) and any value it output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Effect {
+    pub accumulator: isize,
+    pub jump: isize,
+    pub output: Option<isize>,
+}
+
+impl Effect {
+    pub fn next(accumulator: isize) -> Self {
+        Effect { accumulator, jump: 1, output: None }
+    }
+
+    pub fn jump(accumulator: isize, offset: isize) -> Self {
+        Effect { accumulator, jump: offset, output: None }
+    }
+
+    pub fn output(accumulator: isize, value: isize) -> Self {
+        Effect { accumulator, jump: 1, output: Some(value) }
+    }
+}
+
+// Called with the instruction's operand and the accumulator; None halts the machine,
+// e.g. on overflow.
+type Handler = Rc<dyn Fn(isize, isize) -> Option<Effect>>;
+
+#[derive(Clone)]
+struct Opcode {
+    handler: Handler,
+    branches: bool,
+}
+
+pub const BUILT_IN: [&str; 3] = ["acc", "jmp", "nop"];
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum RegisterError {
+    BuiltIn(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterError::BuiltIn(name) => write!(f, "{} is a built-in opcode", name),
+        }
+    }
+}
+
+// The opcodes a console understands: `acc`, `jmp` and `nop`, plus any registered here
+// before a program using them is parsed. Each name is kept once and shared by every
+// instruction parsed with it.
+#[derive(Clone, Default)]
+pub struct InstructionSet {
+    opcodes: HashMap<Rc<str>, Opcode>,
+}
+
+impl InstructionSet {
+    // For an instruction that always moves on the same distance, whatever the
+    // accumulator holds. Fails if the name is one of the built-in opcodes.
+    pub fn with(self, name: &str, handler: impl Fn(isize, isize) -> Option<Effect> + 'static) -> Result<Self, RegisterError> {
+        self.register(name, Rc::new(handler), false)
+    }
+
+    // For an instruction whose jump depends on the accumulator, like a conditional jump.
+    // Revisiting one of these is only a loop if the accumulator is the same as before.
+    pub fn with_branch(self, name: &str, handler: impl Fn(isize, isize) -> Option<Effect> + 'static) -> Result<Self, RegisterError> {
+        self.register(name, Rc::new(handler), true)
+    }
+
+    fn register(mut self, name: &str, handler: Handler, branches: bool) -> Result<Self, RegisterError> {
+        if BUILT_IN.contains(&name) {
+            return Err(RegisterError::BuiltIn(name.to_string()));
+        }
+        self.opcodes.insert(name.into(), Opcode { handler, branches });
+        Ok(self)
+    }
+
+    pub fn branches(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::Custom(name, _) => self.opcodes.get(name).is_some_and(|opcode| opcode.branches),
+            _ => false,
+        }
+    }
+
+    // None if the instruction is not registered in this set or its handler halts the machine.
+    pub fn execute(&self, name: &str, operand: isize, accumulator: isize) -> Option<Effect> {
+        (self.opcodes.get(name)?.handler)(operand, accumulator)
+    }
+
+    // An opcode and a signed operand separated by whitespace, e.g. "jmp -3".
+    pub fn parse(&self, text: &str) -> Result<Instruction, InstructionError> {
+        let mut words = text.split_whitespace();
+        let opcode = words.next().unwrap_or_default();
+        let operand = words.next().ok_or(InstructionError::MissingOperand)?;
+        if let Some(extra) = words.next() {
+            return Err(InstructionError::UnexpectedText(extra.to_string()));
+        }
+        let operand: isize = operand.parse().map_err(|_| InstructionError::InvalidOperand(operand.to_string()))?;
+        match opcode {
+            "acc" => Ok(Instruction::Acc(operand)),
+            "jmp" => Ok(Instruction::Jmp(operand)),
+            "nop" => Ok(Instruction::Nop(operand)),
+            _ => match self.opcodes.get_key_value(opcode) {
+                Some((name, _)) => Ok(Instruction::Custom(Rc::clone(name), operand)),
+                None => Err(InstructionError::UnknownOpcode(opcode.to_string())),
+            },
+        }
+    }
+
+    // Multiply, jump if zero, jump if not zero and output, as an example of extending the console.
+    pub fn extended() -> Self {
+        let build = || -> Result<Self, RegisterError> {
+            InstructionSet::default()
+                .with("mul", |operand, accumulator| accumulator.checked_mul(operand).map(Effect::next))?
+                .with("out", |_, accumulator| Some(Effect::output(accumulator, accumulator)))?
+                .with_branch("jz", |offset, accumulator| Some(if accumulator == 0 { Effect::jump(accumulator, offset) } else { Effect::next(accumulator) }))?
+                .with_branch("jnz", |offset, accumulator| Some(if accumulator != 0 { Effect::jump(accumulator, offset) } else { Effect::next(accumulator) }))
+        };
+        build().expect("none of the extended opcodes are built in")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn registered_opcodes_parsed_as_custom_instructions() {
+        let set = InstructionSet::extended();
+        assert_eq!(set.parse("mul -2"), Ok(Instruction::Custom("mul".into(), -2)));
+        assert_eq!(set.parse("acc +2"), Ok(Instruction::Acc(2)));
+        assert_eq!(set.parse("div +2"), Err(InstructionError::UnknownOpcode("div".to_string())));
+        assert_eq!(InstructionSet::default().parse("mul +2"), Err(InstructionError::UnknownOpcode("mul".to_string())));
+        assert_eq!(Instruction::Custom("mul".into(), -2).to_string(), "mul -2");
+        let (first, second) = (set.parse("mul +1").unwrap(), set.parse("mul +2").unwrap());
+        let (Instruction::Custom(first, _), Instruction::Custom(second, _)) = (first, second) else { panic!("expected custom instructions") };
+        assert!(Rc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn registered_opcodes_executed_by_handler() {
+        let set = InstructionSet::extended();
+        assert_eq!(set.execute("mul", 3, 4), Some(Effect::next(12)));
+        assert_eq!(set.execute("mul", 2, isize::MAX), None);
+        assert_eq!(set.execute("jz", -5, 0), Some(Effect::jump(0, -5)));
+        assert_eq!(set.execute("jz", -5, 1), Some(Effect::next(1)));
+        assert_eq!(set.execute("out", 0, 7), Some(Effect::output(7, 7)));
+        assert_eq!(set.execute("acc", 1, 0), None);
+        assert!(set.branches(&Instruction::Custom("jnz".into(), 1)) && !set.branches(&Instruction::Custom("mul".into(), 1)));
+    }
+
+    #[test]
+    fn built_in_opcodes_cannot_be_replaced() {
+        let error = InstructionSet::default().with("jmp", |_, accumulator| Some(Effect::next(accumulator))).err().unwrap();
+        assert_eq!(error, RegisterError::BuiltIn("jmp".to_string()));
+        assert_eq!(error.to_string(), "jmp is a built-in opcode");
+        let name = String::from("dbl");
+        let set = InstructionSet::default().with(&name, |_, accumulator| Some(Effect::next(accumulator * 2))).unwrap();
+        assert_eq!(set.parse("dbl +0"), Ok(Instruction::Custom("dbl".into(), 0)));
+    }
+}
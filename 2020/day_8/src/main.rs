@@ -1,10 +1,85 @@
 mod handheld_halting;
+mod console_debugger;
+mod boot_repair;
+mod instruction_set;
+mod boot_analysis;
 use handheld_halting::{
-    GameConsole
+    GameConsole,
+    Limits,
 };
+use console_debugger::Debugger;
+use boot_analysis::ControlFlowGraph;
+use boot_repair::{diagnose, Diagnosis, Edits};
+use instruction_set::InstructionSet;
+use std::env;
+use std::io;
 
-fn main() {
-    let mut console = GameConsole::new("test_data/input.txt");
-    println!("The answer to part 1 is {}", console.find_accumulator_before_loop());
-    println!("The answer to part 2 is {}", console.accumulator_value_when_terminating_normally());
+// Usage: day_8 [--debug | --disassemble | --cfg | --loops | --repair [--acc] | --extended <file>]
+fn main() -> Result<(), io::Error> {
+    let mut console = match GameConsole::new("test_data/input.txt") {
+        Ok(console) => console,
+        Err(error) => {
+            eprintln!("{}", error);
+            return Ok(());
+        }
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>()[..] {
+        ["--debug"] => return Debugger::new(console).run_commands(io::stdin().lock(), &mut io::stdout()),
+        ["--disassemble"] => {
+            print!("{}", ControlFlowGraph::new(&console).disassemble());
+            return Ok(());
+        },
+        ["--cfg"] => {
+            print!("{}", ControlFlowGraph::new(&console).to_dot());
+            return Ok(());
+        },
+        ["--extended", filepath] => {
+            // A loop that keeps changing the accumulator is never detected, so give up after a million steps.
+            match GameConsole::load(filepath, InstructionSet::extended()) {
+                Ok(mut program) => {
+                    println!("{}", program.run(&Limits { steps: Some(1_000_000), time: None }));
+                    for value in program.output() {
+                        println!("{}", value);
+                    }
+                },
+                Err(error) => eprintln!("{}", error),
+            }
+            return Ok(());
+        },
+        ["--loops"] => {
+            let cfg = ControlFlowGraph::new(&console);
+            println!("{} blocks", cfg.blocks().len());
+            for members in cfg.loops() {
+                let labels: Vec<String> = members.iter().map(|block| format!("L{}", block)).collect();
+                println!("loop: {}", labels.join(" "));
+            }
+            let unreachable: Vec<String> = cfg.unreachable_instructions().iter().map(|index| index.to_string()).collect();
+            println!("unreachable instructions: {}", unreachable.join(", "));
+            return Ok(());
+        },
+        ["--repair", ref edits @ ..] => {
+            let edits = if edits == ["--acc"] { Edits::JumpsAndAcc } else { Edits::Jumps };
+            match diagnose(console.original(), edits) {
+                Diagnosis::Terminates { accumulator } => println!("The boot code already terminates with {}", accumulator),
+                Diagnosis::Repairable(fixes) => {
+                    for fix in fixes {
+                        println!("{:>4}  {} -> {}  acc={}", fix.index, fix.from, fix.to, fix.accumulator);
+                    }
+                },
+                Diagnosis::NoFix => println!("No single fix makes the boot code terminate"),
+            }
+            return Ok(());
+        },
+        _ => (),
+    }
+    match console.find_accumulator_before_loop() {
+        Some(accumulator) => println!("The answer to part 1 is {}", accumulator),
+        None => println!("The boot code never loops"),
+    }
+    match console.accumulator_value_when_terminating_normally() {
+        Some(accumulator) => println!("The answer to part 2 is {}", accumulator),
+        None => println!("No single fix makes the boot code terminate"),
+    }
+    Ok(())
 }
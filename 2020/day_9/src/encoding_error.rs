@@ -1,82 +1,225 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
-use std::path::Path;
 use std::collections::{
-    HashSet,
+    HashMap,
     VecDeque,
 };
 
-pub fn create_dataset_from(filepath: &str) -> Vec<usize> {
-    let mut dataset = Vec::<usize>::new();
-    if let Ok(lines) = read_lines(filepath) {
-        for line in lines {
-            if let Ok(num) = line {
-                dataset.push(num.parse().unwrap());
-            }
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: \"{}\" is not a number", self.line, self.text)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl From<ReadError> for io::Error {
+    fn from(error: ReadError) -> Self {
+        match error {
+            ReadError::Io(error) => error,
+            ReadError::Parse(error) => io::Error::new(io::ErrorKind::InvalidData, error.to_string()),
         }
     }
-    dataset
 }
 
-pub fn find_misbehaving_num_with_preamble(preamble_num: u8, dataset: &Vec<usize>) -> usize{
-    let mut start_index = 0;
-    let mut end_index = preamble_num as usize - 1;
-    let mut possible_nums_to_add = HashSet::<usize>::new();
-    for index in start_index..=end_index {
-        possible_nums_to_add.insert(dataset[index]);
+pub fn create_dataset_from(filepath: &str) -> io::Result<Vec<usize>> {
+    let file = File::open(filepath)?;
+    Ok(read_numbers(io::BufReader::new(file)).collect::<Result<_, _>>()?)
+}
+
+// One number per line, read as they are needed. Blank lines are skipped but still
+// counted for error positions.
+pub fn read_numbers<R: BufRead>(reader: R) -> impl Iterator<Item = Result<usize, ReadError>> {
+    reader.lines().enumerate().filter_map(|(index, line)| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(line.trim().parse().map_err(|_| ReadError::Parse(ParseError { line: index + 1, text: line.trim().to_string() }))),
+        Err(error) => Some(Err(ReadError::Io(error))),
+    })
+}
+
+// The last `capacity` numbers seen, with a count of each value so a repeated value
+// stays in the window until its last copy leaves.
+pub struct Window {
+    capacity: usize,
+    values: VecDeque<usize>,
+    counts: HashMap<usize, usize>,
+}
+
+impl Window {
+    pub fn new(capacity: usize) -> Self {
+        Window { capacity, values: VecDeque::with_capacity(capacity), counts: HashMap::new() }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.values.len() >= self.capacity
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.counts.contains_key(&value)
     }
-    for index in preamble_num as usize..dataset.len() {
-        if let false = is_num_the_sum_of_two_from_set(dataset[index], &possible_nums_to_add) {
-            return dataset[index];
+
+    // Adds the value, pushing out the oldest one once the window is full.
+    pub fn push(&mut self, value: usize) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.is_full() {
+            let oldest = self.values.pop_front().unwrap();
+            if let Some(count) = self.counts.get_mut(&oldest) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&oldest);
+                }
+            }
         }
-        possible_nums_to_add.remove(&dataset[start_index]);
-        start_index += 1;
-        end_index += 1;
-        possible_nums_to_add.insert(dataset[end_index]);
+        self.values.push_back(value);
+        *self.counts.entry(value).or_insert(0) += 1;
+    }
+
+    // Whether two different values in the window add up to the target.
+    pub fn has_pair_summing_to(&self, target_sum: usize) -> bool {
+        self.counts.keys().any(|&num| {
+            target_sum.checked_sub(num).is_some_and(|other| other != num && self.contains(other))
+        })
     }
-    0
 }
 
-fn is_num_the_sum_of_two_from_set(target_sum: usize, possible_nums: &HashSet<usize>) -> bool {
-    for num in possible_nums {
-        if target_sum < *num {
-            continue;
+// Every number after the preamble that is not the sum of two different numbers among
+// the `preamble` before it, with its index.
+pub struct InvalidNumbers<I> {
+    numbers: I,
+    window: Window,
+    index: usize,
+}
+
+impl<I: Iterator<Item = usize>> Iterator for InvalidNumbers<I> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for num in self.numbers.by_ref() {
+            let index = self.index;
+            self.index += 1;
+            let valid = !self.window.is_full() || self.window.has_pair_summing_to(num);
+            self.window.push(num);
+            if !valid {
+                return Some((index, num));
+            }
         }
-        if *num == (target_sum - num) {
-            continue;
+        None
+    }
+}
+
+pub fn invalid_numbers<I: IntoIterator<Item = usize>>(numbers: I, preamble: usize) -> InvalidNumbers<I::IntoIter> {
+    InvalidNumbers { numbers: numbers.into_iter(), window: Window::new(preamble), index: 0 }
+}
+
+pub fn find_misbehaving_num_with_preamble(preamble: usize, dataset: &[usize]) -> Option<usize> {
+    invalid_numbers(dataset.iter().copied(), preamble).next().map(|(_, num)| num)
+}
+
+// A run of at least two contiguous numbers adding up to the target, from `start` to `end` inclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Weakness {
+    pub start: usize,
+    pub end: usize,
+    pub min: usize,
+    pub max: usize,
+    pub weakness: u128,
+}
+
+// Smallest and largest value in any range, from the extremes of every run of whole blocks
+// whose length is a power of two plus a scan of the partial blocks at either end. Keeping
+// the table to blocks rather than single numbers keeps it a small fraction of the dataset.
+struct RangeExtremes<'a> {
+    dataset: &'a [usize],
+    levels: Vec<Vec<(usize, usize)>>,
+}
+
+const BLOCK: usize = 64;
+
+fn extremes_of(nums: &[usize]) -> (usize, usize) {
+    nums.iter().fold((usize::MAX, 0), |(min, max), &num| (min.min(num), max.max(num)))
+}
+
+fn combine((min1, max1): (usize, usize), (min2, max2): (usize, usize)) -> (usize, usize) {
+    (min1.min(min2), max1.max(max2))
+}
+
+impl<'a> RangeExtremes<'a> {
+    fn new(dataset: &'a [usize]) -> Self {
+        let mut levels = vec![dataset.chunks(BLOCK).map(extremes_of).collect::<Vec<_>>()];
+        let blocks = levels[0].len();
+        let mut width = 1;
+        while width * 2 <= blocks {
+            let previous = levels.last().unwrap();
+            let level = (0..=blocks - width * 2)
+                .map(|start| combine(previous[start], previous[start + width]))
+                .collect();
+            levels.push(level);
+            width *= 2;
         }
-        if possible_nums.contains(&(target_sum - num)) {
-            return true;
+        RangeExtremes { dataset, levels }
+    }
+
+    fn query(&self, start: usize, end: usize) -> (usize, usize) {
+        let (first, last) = (start / BLOCK + 1, end / BLOCK);
+        if first >= last {
+            return extremes_of(&self.dataset[start..=end]);
         }
+        let level = (last - first).ilog2() as usize;
+        let blocks = combine(self.levels[level][first], self.levels[level][last - (1 << level)]);
+        let edges = combine(extremes_of(&self.dataset[start..first * BLOCK]), extremes_of(&self.dataset[last * BLOCK..=end]));
+        combine(blocks, edges)
+    }
+}
+
+// Every range of two or more contiguous numbers adding up to the target, ordered by start
+// then end. A range sums to the target exactly when the running totals before its start
+// and at its end differ by the target, so each end only looks up the starts that fit.
+// Optionally leaves out ranges holding the target itself.
+pub fn find_weaknesses(target_sum: usize, dataset: &[usize], exclude_target: bool) -> Vec<Weakness> {
+    let mut totals_before = vec![0u128];
+    for &num in dataset {
+        totals_before.push(totals_before.last().unwrap() + num as u128);
     }
-    false
-}
-
-pub fn find_weakness_with_target_sum(target_sum: usize, dataset: &Vec<usize>) -> usize {
-    let mut contiguous_window = VecDeque::<usize>::new();
-    let mut total = 0;
-    let mut index = 0;
-    while index < dataset.len() {
-        if total < target_sum {
-            contiguous_window.push_back(dataset[index]);
-            total += dataset[index];
-            index += 1;
-        } else if total > target_sum {
-            let tail = contiguous_window.pop_front().unwrap();
-            total -= tail;
-        } else {
-            break;
+    // The index of the next copy of the target at or after each position.
+    let mut next_target = vec![dataset.len(); dataset.len() + 1];
+    for index in (0..dataset.len()).rev() {
+        next_target[index] = if dataset[index] == target_sum { index } else { next_target[index + 1] };
+    }
+    let extremes = RangeExtremes::new(dataset);
+    let mut starts_by_total = HashMap::<u128, Vec<usize>>::new();
+    let mut weaknesses = vec![];
+    for end in 1..dataset.len() {
+        starts_by_total.entry(totals_before[end - 1]).or_default().push(end - 1);
+        let Some(total) = totals_before[end + 1].checked_sub(target_sum as u128) else {
+            continue;
+        };
+        for &start in starts_by_total.get(&total).into_iter().flatten() {
+            if exclude_target && next_target[start] <= end {
+                continue;
+            }
+            let (min, max) = extremes.query(start, end);
+            weaknesses.push(Weakness { start, end, min, max, weakness: min as u128 + max as u128 });
         }
     }
-    let min = contiguous_window.iter().min().unwrap();
-    let max = contiguous_window.iter().max().unwrap();
-    min + max
+    weaknesses.sort_by_key(|weakness| (weakness.start, weakness.end));
+    weaknesses
 }
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where P: AsRef<Path>, {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+pub fn find_weakness_with_target_sum(target_sum: usize, dataset: &[usize]) -> Option<u128> {
+    find_weaknesses(target_sum, dataset, true).first().map(|weakness| weakness.weakness)
 }
 
 #[cfg(test)]
@@ -85,33 +228,110 @@ mod tests {
     use super::*;
     #[test]
     fn dataset_correctly_created() {
-        let dataset = create_dataset_from("test_data/test1.txt");
+        let dataset = create_dataset_from("test_data/test1.txt").unwrap();
         assert_eq!(20, dataset.len());
         assert_eq!(35, dataset[0]);
         assert_eq!(576, dataset[19]);
     }
 
+    #[test]
+    fn blank_lines_skipped_and_bad_lines_reported() {
+        let numbers: Vec<usize> = read_numbers("1\n\n  2 \n".as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(numbers, vec![1, 2]);
+        let mut numbers = read_numbers("1\n\nx2\n3".as_bytes());
+        assert_eq!(numbers.next().unwrap().unwrap(), 1);
+        let Err(ReadError::Parse(error)) = numbers.next().unwrap() else { panic!("expected a parse error") };
+        assert_eq!(error, ParseError { line: 3, text: "x2".to_string() });
+        assert_eq!(error.to_string(), "line 3: \"x2\" is not a number");
+        assert_eq!(create_dataset_from("test_data/missing.txt").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
     #[test]
     fn test_first_irregular_num_correctly_found() {
-        let dataset = create_dataset_from("test_data/test1.txt");
-        assert_eq!(127, find_misbehaving_num_with_preamble(5, &dataset));
+        let dataset = create_dataset_from("test_data/test1.txt").unwrap();
+        assert_eq!(Some(127), find_misbehaving_num_with_preamble(5, &dataset));
     }
 
     #[test]
     fn correctly_identify_if_a_number_is_the_sum_of_distinct_two_from_a_particular_set() {
-        let mut possible_nums = HashSet::<usize>::new();
+        let mut possible_nums = Window::new(25);
         for num in 1..=25 {
-            possible_nums.insert(num);
+            possible_nums.push(num);
         }
-        assert!(is_num_the_sum_of_two_from_set(26, &possible_nums));
-        assert!(is_num_the_sum_of_two_from_set(49, &possible_nums));
-        assert_eq!(false, is_num_the_sum_of_two_from_set(100, &possible_nums));
-        assert_eq!(false, is_num_the_sum_of_two_from_set(50, &possible_nums));
+        assert!(possible_nums.has_pair_summing_to(26));
+        assert!(possible_nums.has_pair_summing_to(49));
+        assert!(!possible_nums.has_pair_summing_to(100));
+        assert!(!possible_nums.has_pair_summing_to(50));
     }
 
     #[test]
     fn find_weakness_62() {
-        let dataset = create_dataset_from("test_data/test1.txt");
-        assert_eq!(62, find_weakness_with_target_sum(127, &dataset));
+        let dataset = create_dataset_from("test_data/test1.txt").unwrap();
+        assert_eq!(Some(62), find_weakness_with_target_sum(127, &dataset));
+    }
+
+    #[test]
+    fn repeated_values_stay_in_the_window_until_their_last_copy_leaves() {
+        let mut window = Window::new(3);
+        for num in [1, 2, 1, 3] {
+            window.push(num);
+        }
+        assert!(window.contains(1) && !window.has_pair_summing_to(2));
+        assert_eq!(invalid_numbers([1, 2, 1, 3, 4], 3).count(), 0);
+        assert_eq!(invalid_numbers([1, 2, 1, 3, 4, 1], 3).collect::<Vec<_>>(), vec![(5, 1)]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn every_invalid_number_streamed_with_its_index() {
+        let reader = io::BufReader::new(File::open("test_data/test1.txt").unwrap());
+        let invalid: Vec<(usize, usize)> = invalid_numbers(read_numbers(reader).map(Result::unwrap), 5).collect();
+        assert_eq!(invalid, vec![(14, 127)]);
+        assert_eq!(invalid_numbers(create_dataset_from("test_data/test1.txt").unwrap(), 2).next(), Some((2, 15)));
+    }
+
+    #[test]
+    fn preamble_of_any_length_accepted() {
+        let numbers: Vec<usize> = (1..=300).chain([599, 600, 7]).collect();
+        assert_eq!(invalid_numbers(numbers.iter().copied(), 300).collect::<Vec<_>>(), vec![(301, 600)]);
+        assert_eq!(invalid_numbers([5, 6], 0).collect::<Vec<_>>(), vec![(0, 5), (1, 6)]);
+        assert_eq!(find_misbehaving_num_with_preamble(5, &[1, 2, 3, 4, 5, 9]), None);
+    }
+
+    #[test]
+    fn every_contiguous_range_found() {
+        let dataset = create_dataset_from("test_data/test1.txt").unwrap();
+        assert_eq!(find_weaknesses(127, &dataset, false), vec![Weakness { start: 2, end: 5, min: 15, max: 47, weakness: 62 }]);
+        let ranges: Vec<(usize, usize)> = find_weaknesses(5, &[5, 0, 3, 2, 0, 5], false).iter()
+            .map(|weakness| (weakness.start, weakness.end))
+            .collect();
+        assert_eq!(ranges, vec![(0, 1), (1, 3), (1, 4), (2, 3), (2, 4), (4, 5)]);
+        assert_eq!(find_weaknesses(5, &[5, 0, 3, 2, 0, 5], false)[1], Weakness { start: 1, end: 3, min: 0, max: 3, weakness: 3 });
+    }
+
+    #[test]
+    fn ranges_holding_the_target_optionally_excluded() {
+        let ranges: Vec<(usize, usize)> = find_weaknesses(5, &[5, 0, 3, 2, 0, 5], true).iter()
+            .map(|weakness| (weakness.start, weakness.end))
+            .collect();
+        assert_eq!(ranges, vec![(1, 3), (1, 4), (2, 3), (2, 4)]);
+        assert_eq!(find_weakness_with_target_sum(5, &[5, 0]), None);
+        assert_eq!(find_weakness_with_target_sum(5, &[]), None);
+    }
+
+    #[test]
+    fn large_inputs_and_totals_handled() {
+        let dataset = vec![1; 100_000];
+        assert_eq!(find_weaknesses(3, &dataset, true).len(), 99_998);
+        let weaknesses = find_weaknesses(usize::MAX, &[usize::MAX - 1, 1, usize::MAX], true);
+        assert_eq!(weaknesses, vec![Weakness { start: 0, end: 1, min: 1, max: usize::MAX - 1, weakness: usize::MAX as u128 }]);
+    }
+
+    #[test]
+    fn range_extremes_match_a_scan_across_block_boundaries() {
+        let dataset: Vec<usize> = (0..1_000).map(|index| index * 7_919 % 1_009).collect();
+        let extremes = RangeExtremes::new(&dataset);
+        for (start, end) in [(0, 0), (3, 60), (60, 70), (63, 64), (0, 127), (5, 300), (64, 191), (1, 998), (0, 999)] {
+            assert_eq!(extremes.query(start, end), extremes_of(&dataset[start..=end]));
+        }
+    }
+}
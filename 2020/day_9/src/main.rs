@@ -6,8 +6,20 @@ use encoding_error::{
 };
 
 fn main() {
-    let dataset = create_dataset_from("test_data/input.txt");
-    let part_1_answer = find_misbehaving_num_with_preamble(25, &dataset);
+    let dataset = match create_dataset_from("test_data/input.txt") {
+        Ok(dataset) => dataset,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+    let Some(part_1_answer) = find_misbehaving_num_with_preamble(25, &dataset) else {
+        println!("Every number follows the rule");
+        return;
+    };
     println!("The answer to part 1 is {}", part_1_answer);
-    println!("The answer to part 2 is {}", find_weakness_with_target_sum(part_1_answer, &dataset));
-}
\ No newline at end of file
+    match find_weakness_with_target_sum(part_1_answer, &dataset) {
+        Some(weakness) => println!("The answer to part 2 is {}", weakness),
+        None => println!("No contiguous range adds up to {}", part_1_answer),
+    }
+}